@@ -1,16 +1,225 @@
 use crate::error::*;
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Clone)]
 pub enum Data {
-    Hash(HashMap<String, Data>),
+    Null,
+    Hash(IndexMap<String, Data>),
     Array(Vec<Data>),
     String(String),
     Integer(i64),
-    Real(f32),
+    Real(Real),
     Boolean(bool),
 }
 
+impl Data {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Data::Null => "null",
+            Data::Boolean(_) => "boolean",
+            Data::Integer(_) | Data::Real(_) => "number",
+            Data::String(_) => "string",
+            Data::Array(_) => "array",
+            Data::Hash(_) => "object",
+        }
+    }
+
+    /// Only `false` and `null` are falsy, like in jq.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Data::Null | Data::Boolean(false))
+    }
+
+    /// Position of the value's type in jq's ordering:
+    /// null < false < true < numbers < strings < arrays < objects.
+    fn type_order(&self) -> u8 {
+        match self {
+            Data::Null => 0,
+            Data::Boolean(false) => 1,
+            Data::Boolean(true) => 2,
+            Data::Integer(_) | Data::Real(_) => 3,
+            Data::String(_) => 4,
+            Data::Array(_) => 5,
+            Data::Hash(_) => 6,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Data::Integer(n) => Some(*n as f64),
+            Data::Real(n) => Some(n.value()),
+            _ => None,
+        }
+    }
+
+    /// Compares values the way jq does: by type first, then numbers by value, strings by
+    /// codepoint, arrays element-wise and objects by their sorted keys, then values.
+    pub fn compare(&self, other: &Data) -> Ordering {
+        match (self, other) {
+            (Data::Integer(a), Data::Integer(b)) => a.cmp(b),
+            (Data::String(a), Data::String(b)) => a.cmp(b),
+            (Data::Array(a), Data::Array(b)) => {
+                for (x, y) in a.iter().zip(b.iter()) {
+                    let ord = x.compare(y);
+                    if ord != Ordering::Equal {
+                        return ord;
+                    }
+                }
+                a.len().cmp(&b.len())
+            }
+            (Data::Hash(a), Data::Hash(b)) => {
+                let mut a_keys: Vec<&String> = a.keys().collect();
+                let mut b_keys: Vec<&String> = b.keys().collect();
+                a_keys.sort();
+                b_keys.sort();
+                let ord = a_keys.cmp(&b_keys);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                for key in a_keys {
+                    let ord = a[key].compare(&b[key]);
+                    if ord != Ordering::Equal {
+                        return ord;
+                    }
+                }
+                Ordering::Equal
+            }
+            _ => match (self.as_f64(), other.as_f64()) {
+                // NaN sorts below every other number.
+                (Some(a), Some(b)) => match (a.is_nan(), b.is_nan()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Less,
+                    (false, true) => Ordering::Greater,
+                    _ => a.partial_cmp(&b).unwrap(),
+                },
+                _ => self.type_order().cmp(&other.type_order()),
+            },
+        }
+    }
+}
+
+impl PartialEq for Data {
+    fn eq(&self, other: &Data) -> bool {
+        self.compare(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Data {}
+
+impl PartialOrd for Data {
+    fn partial_cmp(&self, other: &Data) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Data {
+    fn cmp(&self, other: &Data) -> Ordering {
+        self.compare(other)
+    }
+}
+
+impl Hash for Data {
+    /// Hashes consistently with `compare`: numbers by value, whatever their
+    /// representation, and objects regardless of the order of their keys.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_order().hash(state);
+        match self {
+            Data::Integer(_) | Data::Real(_) => {
+                let n = self.as_f64().unwrap_or_default();
+                // -0.0 equals 0.0, and every NaN is equal to every other.
+                let n = if n == 0.0 {
+                    0.0
+                } else if n.is_nan() {
+                    f64::NAN
+                } else {
+                    n
+                };
+                n.to_bits().hash(state);
+            }
+            Data::String(s) => s.hash(state),
+            Data::Array(arr) => arr.hash(state),
+            Data::Hash(map) => {
+                let mut entries: Vec<(&String, &Data)> = map.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                entries.hash(state);
+            }
+            Data::Null | Data::Boolean(_) => {}
+        }
+    }
+}
+
+/// Double-precision number which remembers the literal it was read from,
+/// so that untouched values print back exactly as they were written.
+#[derive(Clone)]
+pub struct Real {
+    value: f64,
+    literal: Option<String>,
+}
+
+impl Real {
+    pub fn new(value: f64) -> Self {
+        Self {
+            value,
+            literal: None,
+        }
+    }
+
+    pub fn with_literal(value: f64, literal: &str) -> Self {
+        if !value.is_finite() || !Real::is_plain_literal(literal) {
+            return Real::new(value);
+        }
+        Self {
+            value,
+            literal: Some(literal.to_string()),
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    fn is_plain_literal(literal: &str) -> bool {
+        let digits = literal.strip_prefix('-').unwrap_or(literal);
+        digits.starts_with(|c: char| c.is_ascii_digit())
+            && digits.ends_with(|c: char| c.is_ascii_digit())
+            && digits
+                .chars()
+                .all(|c| c.is_ascii_digit() || ".eE+-".contains(c))
+    }
+}
+
+impl std::fmt::Display for Real {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        if let Some(literal) = &self.literal {
+            return write!(f, "{}", literal);
+        }
+        let value = self.value;
+        if value.is_nan() {
+            write!(f, "null")
+        } else if value.is_infinite() {
+            let max = if value > 0.0 { f64::MAX } else { f64::MIN };
+            write!(f, "{}", Real::new(max))
+        } else if value != 0.0 && (value.abs() >= 1e17 || value.abs() < 1e-5) {
+            let formatted = format!("{:e}", value);
+            match formatted.split_once('e') {
+                Some((mantissa, exp)) if !exp.starts_with('-') => {
+                    write!(f, "{}e+{}", mantissa, exp)
+                }
+                _ => write!(f, "{}", formatted),
+            }
+        } else {
+            write!(f, "{}", value)
+        }
+    }
+}
+
+impl std::fmt::Debug for Real {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self)
+    }
+}
+
 pub fn load_file(filename: &str) -> Result<Data, Error> {
     let fpath = std::path::Path::new(filename);
     match fpath.extension() {
@@ -25,9 +234,50 @@ pub fn load_file(filename: &str) -> Result<Data, Error> {
     }
 }
 
+/// Loads a single value of unknown format: JSON, falling back to YAML.
+pub fn load_str(contents: &str) -> Result<Data, Error> {
+    let mut values = load_str_stream(contents)?;
+    match values.len() {
+        1 => Ok(values.remove(0)),
+        n => Err(Error::Dataset(format!(
+            "expected a single value, got {}",
+            n
+        ))),
+    }
+}
+
+/// Loads data of unknown format, such as standard input, as a stream of values.
+/// Concatenated or newline-delimited JSON values are read one by one; input which
+/// does not start with a JSON value followed by another one (or the end) is YAML.
+pub fn load_str_stream(contents: &str) -> Result<Vec<Data>, Error> {
+    let mut reader = JsonReader::new(contents);
+    reader.skip_whitespace();
+    if reader.peek().is_none() {
+        return Ok(Vec::new());
+    }
+    let first = match reader.read_value() {
+        Ok(data) => data,
+        Err(_) => return Ok(vec![load_yaml_str(contents)?]),
+    };
+    reader.skip_whitespace();
+    if !reader.at_end() && !reader.at_value_start() {
+        return Ok(vec![load_yaml_str(contents)?]);
+    }
+    let mut values = vec![first];
+    while !reader.at_end() {
+        values.push(reader.read_value()?);
+        reader.skip_whitespace();
+    }
+    Ok(values)
+}
+
 fn load_yaml(filename: &str) -> Result<Data, Error> {
     let contents = std::fs::read_to_string(filename)?;
-    let raw = yaml_rust::YamlLoader::load_from_str(&contents)?;
+    load_yaml_str(&contents)
+}
+
+fn load_yaml_str(contents: &str) -> Result<Data, Error> {
+    let raw = yaml_rust::YamlLoader::load_from_str(contents)?;
 
     if raw.len() == 1 {
         parse_yaml(raw[0].clone())
@@ -43,13 +293,13 @@ fn load_yaml(filename: &str) -> Result<Data, Error> {
 fn parse_yaml(raw: yaml_rust::yaml::Yaml) -> Result<Data, Error> {
     use yaml_rust::yaml::Yaml;
     match raw {
-        Yaml::Real(string) => Ok(Data::Real(string.parse::<f32>().unwrap())),
+        Yaml::Real(string) => Ok(Data::Real(parse_yaml_real(&string)?)),
         Yaml::Integer(num) => Ok(Data::Integer(num)),
         Yaml::String(string) => Ok(Data::String(string)),
         Yaml::Hash(map) => {
-            let mut hash: HashMap<String, Data> = HashMap::new();
+            let mut hash: IndexMap<String, Data> = IndexMap::new();
             for (key, value) in map {
-                hash.insert(key.as_str().unwrap().to_string(), parse_yaml(value)?);
+                hash.insert(parse_yaml_key(key)?, parse_yaml(value)?);
             }
             Ok(Data::Hash(hash))
         }
@@ -61,48 +311,540 @@ fn parse_yaml(raw: yaml_rust::yaml::Yaml) -> Result<Data, Error> {
             Ok(Data::Array(arr))
         }
         Yaml::Boolean(b) => Ok(Data::Boolean(b)),
-        _ => todo!("Not covered"),
+        Yaml::Null => Ok(Data::Null),
+        Yaml::Alias(_) => Err(Error::Dataset("YAML aliases are not supported".to_string())),
+        Yaml::BadValue => Err(Error::Dataset("invalid YAML value".to_string())),
     }
 }
 
+/// Stringifies a scalar mapping key the way yq does, e.g. `~` becomes `null`.
+fn parse_yaml_key(key: yaml_rust::yaml::Yaml) -> Result<String, Error> {
+    use yaml_rust::yaml::Yaml;
+    match key {
+        Yaml::String(string) | Yaml::Real(string) => Ok(string),
+        Yaml::Integer(num) => Ok(num.to_string()),
+        Yaml::Boolean(b) => Ok(b.to_string()),
+        Yaml::Null => Ok("null".to_string()),
+        _ => Err(Error::Dataset(
+            "YAML mapping keys must be scalars".to_string(),
+        )),
+    }
+}
+
+fn parse_yaml_real(string: &str) -> Result<Real, Error> {
+    let value = match string {
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => f64::INFINITY,
+        "-.inf" | "-.Inf" | "-.INF" => f64::NEG_INFINITY,
+        ".nan" | ".NaN" | ".NAN" => f64::NAN,
+        _ => string
+            .parse::<f64>()
+            .map_err(|_| Error::Dataset(format!("invalid number: {}", string)))?,
+    };
+    Ok(Real::with_literal(value, string))
+}
+
 fn load_json(filename: &str) -> Result<Data, Error> {
     let contents = std::fs::read_to_string(filename)?;
-    let raw = json::parse(&contents)?;
-    parse_json(raw)
+    parse_json(&contents)
+}
+
+fn parse_json(contents: &str) -> Result<Data, Error> {
+    let mut reader = JsonReader::new(contents);
+    let data = reader.read_value()?;
+    reader.skip_whitespace();
+    if reader.at_end() {
+        Ok(data)
+    } else {
+        Err(reader.error("unexpected trailing characters"))
+    }
+}
+
+/// Reads JSON text straight into `Data`, keeping the source text of numbers
+/// so that reals print back exactly as they were written.
+struct JsonReader<'a> {
+    source: &'a str,
+    position: usize,
 }
 
-fn parse_json(raw: json::JsonValue) -> Result<Data, Error> {
-    return if raw.is_array() {
+impl<'a> JsonReader<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn at_end(&self) -> bool {
+        self.position == self.source.len()
+    }
+
+    fn at_value_start(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some('{' | '[' | '"' | '-' | '0'..='9' | 't' | 'f' | 'n')
+        )
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Dataset(format!(
+            "Unable to parse data because: {} at offset {}",
+            message, self.position
+        ))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected '{}', got '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}', got end of input", expected))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn read_value(&mut self) -> Result<Data, Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.read_object(),
+            Some('[') => self.read_array(),
+            Some('"') => Ok(Data::String(self.read_string()?)),
+            Some('-' | '0'..='9') => self.read_number(),
+            Some('t') => self.read_word("true", Data::Boolean(true)),
+            Some('f') => self.read_word("false", Data::Boolean(false)),
+            Some('n') => self.read_word("null", Data::Null),
+            Some(c) => Err(self.error(&format!("unexpected character '{}'", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn read_word(&mut self, word: &str, value: Data) -> Result<Data, Error> {
+        if !self.source[self.position..].starts_with(word) {
+            return Err(self.error("unexpected word"));
+        }
+        self.position += word.len();
+        Ok(value)
+    }
+
+    fn read_object(&mut self) -> Result<Data, Error> {
+        self.expect('{')?;
+        let mut hash: IndexMap<String, Data> = IndexMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(Data::Hash(hash));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.read_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            hash.insert(key, self.read_value()?);
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some('}') => return Ok(Data::Hash(hash)),
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+
+    fn read_array(&mut self) -> Result<Data, Error> {
+        self.expect('[')?;
         let mut arr: Vec<Data> = Vec::new();
-        for item in raw.members() {
-            arr.push(parse_json(item.clone())?);
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+            return Ok(Data::Array(arr));
         }
-        Ok(Data::Array(arr))
-    } else if raw.is_object() {
-        let mut hash: HashMap<String, Data> = HashMap::new();
-        for (key, value) in raw.entries() {
-            hash.insert(key.to_string(), parse_json(value.clone())?);
-        }
-        Ok(Data::Hash(hash))
-    } else if raw.is_number() {
-        let num = raw.as_i64();
-        if let Some(n) = num {
-            Ok(Data::Integer(n))
+        loop {
+            arr.push(self.read_value()?);
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => continue,
+                Some(']') => return Ok(Data::Array(arr)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn skip_digits(&mut self) -> usize {
+        let start = self.position;
+        while let Some('0'..='9') = self.peek() {
+            self.position += 1;
+        }
+        self.position - start
+    }
+
+    /// Integers that fit into `i64` become `Data::Integer`, anything else a
+    /// real holding on to its literal.
+    fn read_number(&mut self) -> Result<Data, Error> {
+        let start = self.position;
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            Some('0') => self.position += 1,
+            Some('1'..='9') => {
+                self.skip_digits();
+            }
+            _ => return Err(self.error("expected digit")),
+        }
+        let mut integral = true;
+        if self.peek() == Some('.') {
+            self.position += 1;
+            integral = false;
+            if self.skip_digits() == 0 {
+                return Err(self.error("expected digit after decimal point"));
+            }
+        }
+        if let Some('e' | 'E') = self.peek() {
+            self.position += 1;
+            integral = false;
+            if let Some('+' | '-') = self.peek() {
+                self.position += 1;
+            }
+            if self.skip_digits() == 0 {
+                return Err(self.error("expected digit in exponent"));
+            }
+        }
+        let literal = &self.source[start..self.position];
+        if integral {
+            if let Ok(n) = literal.parse::<i64>() {
+                return Ok(Data::Integer(n));
+            }
+        }
+        let value = literal
+            .parse::<f64>()
+            .map_err(|_| self.error(&format!("invalid number: {}", literal)))?;
+        Ok(Data::Real(Real::with_literal(value, literal)))
+    }
+
+    fn read_string(&mut self) -> Result<String, Error> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(self.read_escape()?),
+                Some(c) if c < ' ' => return Err(self.error("unescaped control character")),
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn read_escape(&mut self) -> Result<char, Error> {
+        Ok(match self.bump() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                let high = self.read_hex()?;
+                if !(0xD800..0xDC00).contains(&high) {
+                    char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER)
+                } else if self.source[self.position..].starts_with("\\u") {
+                    self.position += 2;
+                    let low = self.read_hex()?;
+                    if (0xDC00..0xE000).contains(&low) {
+                        let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                        char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                    } else {
+                        return Err(self.error("invalid surrogate pair"));
+                    }
+                } else {
+                    char::REPLACEMENT_CHARACTER
+                }
+            }
+            _ => return Err(self.error("invalid escape")),
+        })
+    }
+
+    fn read_hex(&mut self) -> Result<u32, Error> {
+        match self.source.get(self.position..self.position + 4) {
+            Some(digits) if digits.chars().all(|c| c.is_ascii_hexdigit()) => {
+                self.position += 4;
+                Ok(u32::from_str_radix(digits, 16).unwrap())
+            }
+            _ => Err(self.error("expected four hex digits")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_json_null() {
+        if let Ok(Data::Hash(map)) = parse_json("{\"name\": null}") {
+            assert!(matches!(map.get("name"), Some(Data::Null)), "expected null");
+        } else {
+            panic!("expected hash");
+        }
+    }
+
+    #[test]
+    fn keeps_json_key_order() {
+        if let Ok(Data::Hash(map)) = parse_json("{\"z\": 1, \"a\": 2, \"m\": 3}") {
+            let keys: Vec<&String> = map.keys().collect();
+            assert_eq!(keys, vec!["z", "a", "m"]);
         } else {
-            let num = raw.as_f32();
-            if let Some(n) = num {
-                Ok(Data::Real(n as f32))
-            } else {
-                Err(Error::Dataset(format!("invalid number: {:?}", num)))
+            panic!("expected hash");
+        }
+    }
+
+    #[test]
+    fn keeps_yaml_key_order() {
+        let raw = yaml_rust::YamlLoader::load_from_str("z: 1\na: 2\nm: 3\n").unwrap();
+        if let Ok(Data::Hash(map)) = parse_yaml(raw[0].clone()) {
+            let keys: Vec<&String> = map.keys().collect();
+            assert_eq!(keys, vec!["z", "a", "m"]);
+        } else {
+            panic!("expected hash");
+        }
+    }
+
+    #[test]
+    fn keeps_json_real_literals() {
+        if let Ok(Data::Array(arr)) = parse_json("[58.761557, -123.367259, 1.10]") {
+            let printed: Vec<String> = arr.iter().map(|n| format!("{:?}", n)).collect();
+            assert_eq!(
+                printed,
+                vec!["Real(58.761557)", "Real(-123.367259)", "Real(1.10)"]
+            );
+        } else {
+            panic!("expected array");
+        }
+    }
+
+    #[test]
+    fn parses_large_json_integers_as_reals() {
+        if let Ok(Data::Real(n)) = parse_json("12345678901234567890") {
+            assert_eq!(format!("{}", n), "12345678901234567890");
+        } else {
+            panic!("expected real");
+        }
+    }
+
+    #[test]
+    fn keeps_json_exponent_literals() {
+        if let Ok(Data::Array(arr)) = parse_json("[1E2, 0.10e1, -2.5e-3, 1e+5]") {
+            let printed: Vec<String> = arr.iter().map(|n| format!("{:?}", n)).collect();
+            assert_eq!(
+                printed,
+                vec!["Real(1E2)", "Real(0.10e1)", "Real(-2.5e-3)", "Real(1e+5)"]
+            );
+        } else {
+            panic!("expected array");
+        }
+    }
+
+    #[test]
+    fn keeps_json_trailing_zeros() {
+        if let Ok(Data::Array(arr)) = parse_json("[1.0, 0.500, -3.000]") {
+            let printed: Vec<String> = arr.iter().map(|n| format!("{:?}", n)).collect();
+            assert_eq!(printed, vec!["Real(1.0)", "Real(0.500)", "Real(-3.000)"]);
+        } else {
+            panic!("expected array");
+        }
+    }
+
+    #[test]
+    fn keeps_json_integers_beyond_i64() {
+        if let Ok(Data::Array(arr)) = parse_json("[100000000000000000000000, -9223372036854775809]")
+        {
+            let printed: Vec<String> = arr.iter().map(|n| format!("{:?}", n)).collect();
+            assert_eq!(
+                printed,
+                vec![
+                    "Real(100000000000000000000000)",
+                    "Real(-9223372036854775809)"
+                ]
+            );
+        } else {
+            panic!("expected array");
+        }
+    }
+
+    #[test]
+    fn parses_json_strings() {
+        assert!(
+            matches!(
+                parse_json("\"a\\n\\u00e9\\ud83d\\ude00\""),
+                Ok(Data::String(s)) if s == "a\n\u{e9}\u{1f600}"
+            ),
+            "expected unescaped string"
+        );
+        for source in ["\"a", "\"\\x\"", "[1,]", "01", "1.", "{\"a\" 1}", "[1] 2"] {
+            assert!(
+                parse_json(source).is_err(),
+                "{} should not be a success",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn parses_yaml_special_reals() {
+        let raw = yaml_rust::YamlLoader::load_from_str("[.inf, -.inf, .nan, 0.5]").unwrap();
+        if let Ok(Data::Array(arr)) = parse_yaml(raw[0].clone()) {
+            let values: Vec<f64> = arr
+                .iter()
+                .map(|n| match n {
+                    Data::Real(r) => r.value(),
+                    _ => panic!("expected real"),
+                })
+                .collect();
+            assert_eq!(values[0], f64::INFINITY);
+            assert_eq!(values[1], f64::NEG_INFINITY);
+            assert!(values[2].is_nan(), "expected nan");
+            assert_eq!(values[3], 0.5);
+        } else {
+            panic!("expected array");
+        }
+    }
+
+    #[test]
+    fn formats_computed_reals() {
+        assert_eq!(format!("{}", Real::new(0.5)), "0.5");
+        assert_eq!(format!("{}", Real::new(3.0)), "3");
+        assert_eq!(format!("{}", Real::new(1e300)), "1e+300");
+        assert_eq!(format!("{}", Real::new(1.5e-7)), "1.5e-7");
+        assert_eq!(format!("{}", Real::new(f64::NAN)), "null");
+    }
+
+    #[test]
+    fn loads_json_or_yaml_strings() {
+        assert!(
+            matches!(load_str("[1, 2]"), Ok(Data::Array(_))),
+            "expected JSON array"
+        );
+        assert!(
+            matches!(load_str("a: 1\nb: 2\n"), Ok(Data::Hash(_))),
+            "expected YAML hash"
+        );
+        assert!(load_str("[1, 2").is_err(), "should not be a success");
+        assert!(load_str("1 2").is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn loads_json_streams() {
+        let values = load_str_stream("{\"a\": 1}\n{\"a\": 2}{\"a\":3} 4\n").unwrap();
+        assert_eq!(values.len(), 4);
+        assert_eq!(
+            load_str_stream("1 2").unwrap(),
+            vec![Data::Integer(1), Data::Integer(2)]
+        );
+        assert_eq!(load_str_stream(" \n").unwrap(), vec![]);
+        assert!(
+            load_str_stream("1\n[2,").is_err(),
+            "should not be a success"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_yaml_for_non_json_streams() {
+        for source in ["a b", "\"a\": 1", "1: a", "- 1\n- 2"] {
+            let values = load_str_stream(source).unwrap();
+            assert_eq!(values.len(), 1, "{} should be one YAML document", source);
+            assert!(
+                !matches!(values[0], Data::Integer(_)),
+                "{} should not be read as JSON",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn orders_types_like_jq() {
+        let values = load_str("[null, false, true, 1, \"a\", [], {}]").unwrap();
+        if let Data::Array(arr) = values {
+            for pair in arr.windows(2) {
+                assert_eq!(pair[0].compare(&pair[1]), Ordering::Less);
             }
+        } else {
+            panic!("expected array");
         }
-    } else if raw.is_boolean() {
-        Ok(Data::Boolean(raw.as_bool().unwrap()))
-    } else if raw.is_string() {
-        Ok(Data::String(raw.as_str().unwrap().to_string()))
-    } else {
-        Err(Error::Dataset(
-            "JSON is not an object or an array".to_string(),
-        ))
-    };
+    }
+
+    #[test]
+    fn compares_numbers_by_value() {
+        let real = Data::Real(Real::with_literal(1.0, "1.0"));
+        assert_eq!(Data::Integer(1).compare(&real), Ordering::Equal);
+        assert_eq!(Data::Integer(2).compare(&real), Ordering::Greater);
+    }
+
+    #[test]
+    fn hashes_equal_values_alike() {
+        use std::collections::HashSet;
+        let values = load_str("[1, 1.0, {\"a\": 1, \"b\": 2}, {\"b\": 2, \"a\": 1}, [1]]").unwrap();
+        if let Data::Array(arr) = values {
+            assert_eq!(arr[0], arr[1]);
+            assert!(arr[4] < arr[2], "arrays sort before objects");
+            let unique: HashSet<Data> = arr.into_iter().collect();
+            assert_eq!(unique.len(), 3);
+        } else {
+            panic!("expected array");
+        }
+    }
+
+    #[test]
+    fn compares_objects_by_keys_then_values() {
+        let a = load_str("{\"a\": 2}").unwrap();
+        let b = load_str("{\"a\": 1, \"b\": 0}").unwrap();
+        let c = load_str("{\"a\": 1}").unwrap();
+        assert_eq!(a.compare(&b), Ordering::Less);
+        assert_eq!(a.compare(&c), Ordering::Greater);
+    }
+
+    #[test]
+    fn parses_yaml_null() {
+        let raw = yaml_rust::YamlLoader::load_from_str("- ~\n- null\n").unwrap();
+        if let Ok(Data::Array(arr)) = parse_yaml(raw[0].clone()) {
+            assert_eq!(arr.len(), 2);
+            assert!(matches!(arr[0], Data::Null), "expected null");
+            assert!(matches!(arr[1], Data::Null), "expected null");
+        } else {
+            panic!("expected array");
+        }
+    }
+
+    #[test]
+    fn stringifies_yaml_scalar_keys() {
+        let raw = yaml_rust::YamlLoader::load_from_str("~: a\n1: b\ntrue: c\n1.5: d\n").unwrap();
+        if let Ok(Data::Hash(map)) = parse_yaml(raw[0].clone()) {
+            let keys: Vec<&String> = map.keys().collect();
+            assert_eq!(keys, vec!["null", "1", "true", "1.5"]);
+        } else {
+            panic!("expected hash");
+        }
+    }
+
+    #[test]
+    fn rejects_yaml_collection_keys() {
+        let raw = yaml_rust::YamlLoader::load_from_str("? [a]\n: b\n").unwrap();
+        assert!(
+            matches!(parse_yaml(raw[0].clone()), Err(Error::Dataset(_))),
+            "expected dataset error"
+        );
+    }
 }
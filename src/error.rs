@@ -1,25 +1,53 @@
+use crate::dataset::Data;
+use crate::output::*;
+
 #[derive(Debug)]
 pub enum Error {
     Dataset(String),
     Lexer(String),
     Parser(String),
-    Filter,
+    Filter(String),
+    /// Raised by `error(value)`, carrying the value to a `catch` handler.
+    Raised(Data),
 }
 
-impl From<std::io::Error> for Error {
-    fn from(e: std::io::Error) -> Self {
-        return Error::Dataset(format!("Unable to parse data because: {}", e));
+impl Error {
+    /// The value a `catch` handler receives for the error.
+    pub fn value(self) -> Data {
+        match self {
+            Error::Raised(value) => value,
+            Error::Dataset(msg) | Error::Lexer(msg) | Error::Parser(msg) | Error::Filter(msg) => {
+                Data::String(msg)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Error::Dataset(err) => write!(f, "[Dataset] ERROR: {}", err),
+            Error::Lexer(err) => write!(f, "[Lexer] ERROR: {}", err),
+            Error::Parser(err) => write!(f, "[Parser] ERROR: {}", err),
+            Error::Filter(err) => write!(f, "[Filter] ERROR: {}", err),
+            Error::Raised(Data::String(err)) => write!(f, "[Filter] ERROR: {}", err),
+            Error::Raised(value) => write!(
+                f,
+                "[Filter] ERROR: {} (not a string)",
+                to_json(value, JsonStyle::Compact)
+            ),
+        }
     }
 }
 
-impl From<json::Error> for Error {
-    fn from(e: json::Error) -> Self {
-        return Error::Dataset(format!("Unable to parse data because: {}", e));
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Dataset(format!("Unable to parse data because: {}", e))
     }
 }
 
 impl From<yaml_rust::ScanError> for Error {
     fn from(e: yaml_rust::ScanError) -> Self {
-        return Error::Dataset(format!("Unable to parse data because: {}", e));
+        Error::Dataset(format!("Unable to parse data because: {}", e))
     }
 }
@@ -0,0 +1,200 @@
+use super::*;
+use crate::output::*;
+use indexmap::IndexMap;
+use std::cmp::Ordering;
+
+/// Describes a value for error messages, e.g. `number (1)`.
+pub fn describe(data: &Data) -> String {
+    let mut json = to_json(data, JsonStyle::Compact);
+    if json.chars().count() > 11 {
+        json = json.chars().take(10).collect::<String>() + "...";
+    }
+    format!("{} ({})", data.type_name(), json)
+}
+
+fn real(n: f64) -> Data {
+    Data::Real(Real::new(n))
+}
+
+fn number(data: &Data) -> Option<f64> {
+    match data {
+        Data::Integer(n) => Some(*n as f64),
+        Data::Real(n) => Some(n.value()),
+        _ => None,
+    }
+}
+
+/// Applies an integer operation, falling back to floating point when it would overflow.
+fn numeric(
+    lhs: &Data,
+    rhs: &Data,
+    int_op: fn(i64, i64) -> Option<i64>,
+    real_op: fn(f64, f64) -> f64,
+) -> Option<Data> {
+    if let (Data::Integer(a), Data::Integer(b)) = (lhs, rhs) {
+        if let Some(n) = int_op(*a, *b) {
+            return Some(Data::Integer(n));
+        }
+    }
+    Some(real(real_op(number(lhs)?, number(rhs)?)))
+}
+
+pub fn add(lhs: Data, rhs: &Data) -> Result<Data, Error> {
+    if let Some(n) = numeric(&lhs, rhs, i64::checked_add, |a, b| a + b) {
+        return Ok(n);
+    }
+    match (lhs, rhs) {
+        (Data::Null, rhs) => Ok(rhs.clone()),
+        (lhs, Data::Null) => Ok(lhs),
+        (Data::String(a), Data::String(b)) => Ok(Data::String(a + b)),
+        (Data::Array(mut a), Data::Array(b)) => {
+            a.extend(b.iter().cloned());
+            Ok(Data::Array(a))
+        }
+        (Data::Hash(mut a), Data::Hash(b)) => {
+            for (key, value) in b {
+                a.insert(key.to_string(), value.clone());
+            }
+            Ok(Data::Hash(a))
+        }
+        (lhs, rhs) => Err(Error::Filter(format!(
+            "{} and {} cannot be added",
+            describe(&lhs),
+            describe(rhs)
+        ))),
+    }
+}
+
+pub fn subtract(lhs: Data, rhs: &Data) -> Result<Data, Error> {
+    if let Some(n) = numeric(&lhs, rhs, i64::checked_sub, |a, b| a - b) {
+        return Ok(n);
+    }
+    match (lhs, rhs) {
+        (Data::Array(a), Data::Array(b)) => Ok(Data::Array(
+            a.into_iter()
+                .filter(|x| !b.iter().any(|y| x.compare(y) == Ordering::Equal))
+                .collect(),
+        )),
+        (lhs, rhs) => Err(Error::Filter(format!(
+            "{} and {} cannot be subtracted",
+            describe(&lhs),
+            describe(rhs)
+        ))),
+    }
+}
+
+fn deep_merge(
+    mut lhs: IndexMap<String, Data>,
+    rhs: &IndexMap<String, Data>,
+) -> IndexMap<String, Data> {
+    for (key, value) in rhs {
+        let merged = match (lhs.get_mut(key), value) {
+            (Some(Data::Hash(a)), Data::Hash(b)) => Data::Hash(deep_merge(std::mem::take(a), b)),
+            _ => value.clone(),
+        };
+        lhs.insert(key.to_string(), merged);
+    }
+    lhs
+}
+
+/// Longest string that `string * number` is allowed to build.
+const MAX_REPEAT_LEN: usize = i32::MAX as usize;
+
+/// Repeats a string like jq does: fractional counts are truncated, but any
+/// count up to 1 still gives one copy.
+fn repeat(string: &str, times: f64) -> Result<Data, Error> {
+    if times.is_nan() || times <= 0.0 {
+        return Ok(Data::Null);
+    }
+    let count = (times as usize).max(1);
+    match string.len().checked_mul(count) {
+        Some(len) if len <= MAX_REPEAT_LEN => Ok(Data::String(string.repeat(count))),
+        _ => Err(Error::Filter(format!(
+            "{} cannot be repeated {} times",
+            describe(&Data::String(string.to_string())),
+            times
+        ))),
+    }
+}
+
+pub fn multiply(lhs: Data, rhs: &Data) -> Result<Data, Error> {
+    if let Some(n) = numeric(&lhs, rhs, i64::checked_mul, |a, b| a * b) {
+        return Ok(n);
+    }
+    match (lhs, rhs) {
+        (Data::String(s), rhs) if number(rhs).is_some() => repeat(&s, number(rhs).unwrap()),
+        (lhs, Data::String(s)) if number(&lhs).is_some() => repeat(s, number(&lhs).unwrap()),
+        (Data::Hash(a), Data::Hash(b)) => Ok(Data::Hash(deep_merge(a, b))),
+        (lhs, rhs) => Err(Error::Filter(format!(
+            "{} and {} cannot be multiplied",
+            describe(&lhs),
+            describe(rhs)
+        ))),
+    }
+}
+
+pub fn divide(lhs: Data, rhs: &Data) -> Result<Data, Error> {
+    if let (Some(a), Some(b)) = (number(&lhs), number(rhs)) {
+        if b == 0.0 {
+            return Err(Error::Filter(format!(
+                "{} and {} cannot be divided because the divisor is zero",
+                describe(&lhs),
+                describe(rhs)
+            )));
+        }
+        if let (Data::Integer(x), Data::Integer(y)) = (&lhs, rhs) {
+            if let Some(0) = x.checked_rem(*y) {
+                return Ok(Data::Integer(x / y));
+            }
+        }
+        return Ok(real(a / b));
+    }
+    match (lhs, rhs) {
+        (Data::String(s), Data::String(sep)) => Ok(Data::Array(if s.is_empty() {
+            Vec::new()
+        } else if sep.is_empty() {
+            s.chars().map(|c| Data::String(c.to_string())).collect()
+        } else {
+            s.split(sep.as_str())
+                .map(|part| Data::String(part.to_string()))
+                .collect()
+        })),
+        (lhs, rhs) => Err(Error::Filter(format!(
+            "{} and {} cannot be divided",
+            describe(&lhs),
+            describe(rhs)
+        ))),
+    }
+}
+
+pub fn modulo(lhs: Data, rhs: &Data) -> Result<Data, Error> {
+    if let (Some(a), Some(b)) = (number(&lhs), number(rhs)) {
+        let (a, b) = (a as i64, b as i64);
+        if b == 0 {
+            return Err(Error::Filter(format!(
+                "{} and {} cannot be divided because the divisor is zero",
+                describe(&lhs),
+                describe(rhs)
+            )));
+        }
+        return Ok(Data::Integer(a.checked_rem(b).unwrap_or(0)));
+    }
+    Err(Error::Filter(format!(
+        "{} and {} cannot be divided",
+        describe(&lhs),
+        describe(rhs)
+    )))
+}
+
+pub fn negate(data: Data) -> Result<Data, Error> {
+    match data {
+        Data::Integer(n) => Ok(n
+            .checked_neg()
+            .map_or_else(|| real(-(n as f64)), Data::Integer)),
+        Data::Real(n) => Ok(real(-n.value())),
+        _ => Err(Error::Filter(format!(
+            "{} cannot be negated",
+            describe(&data)
+        ))),
+    }
+}
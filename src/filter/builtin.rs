@@ -0,0 +1,957 @@
+use super::*;
+use crate::output::*;
+use indexmap::IndexMap;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+type Constructor = fn(Vec<FilterExpression>) -> FilterExpression;
+
+/// Functions available to every filter, by name and arity, with the expression
+/// a call of each resolves to. User definitions with the same name and arity shadow them.
+const BUILTINS: &[(&str, usize, Constructor)] = &[
+    ("empty", 0, |_| FilterExpression::Filter(FilterType::Empty)),
+    ("not", 0, |_| FilterExpression::Filter(FilterType::Not)),
+    ("keys", 0, |_| FilterExpression::Filter(FilterType::Keys)),
+    ("keys_unsorted", 0, |_| {
+        FilterExpression::Filter(FilterType::KeysUnsorted)
+    }),
+    ("error", 0, |_| FilterExpression::Filter(FilterType::Error)),
+    // `error(message)` is defined as `message | error`.
+    ("error", 1, |mut args| {
+        FilterExpression::binary(
+            Operator::Pipe,
+            args.remove(0),
+            FilterExpression::Filter(FilterType::Error),
+        )
+    }),
+    ("select", 1, |mut args| {
+        FilterExpression::Select(Rc::new(args.remove(0)))
+    }),
+    ("map", 1, |mut args| {
+        FilterExpression::Map(Rc::new(args.remove(0)))
+    }),
+    ("map_values", 1, |mut args| {
+        FilterExpression::MapValues(Rc::new(args.remove(0)))
+    }),
+    ("recurse", 0, |_| {
+        FilterExpression::Filter(FilterType::Recurse)
+    }),
+    ("recurse", 1, |mut args| {
+        FilterExpression::Recurse(Rc::new(args.remove(0)), None)
+    }),
+    ("recurse", 2, |mut args| {
+        let step = Rc::new(args.remove(0));
+        FilterExpression::Recurse(step, Some(Rc::new(args.remove(0))))
+    }),
+    ("length", 0, |args| call(Builtin::Length, args)),
+    ("utf8bytelength", 0, |args| {
+        call(Builtin::Utf8ByteLength, args)
+    }),
+    ("type", 0, |args| call(Builtin::Type, args)),
+    ("tostring", 0, |args| call(Builtin::ToString, args)),
+    ("tonumber", 0, |args| call(Builtin::ToNumber, args)),
+    ("add", 0, |args| call(Builtin::Add, args)),
+    ("any", 0, |args| call(Builtin::Any, args)),
+    ("any", 1, |args| call(Builtin::Any, args)),
+    ("any", 2, |args| call(Builtin::Any, args)),
+    ("all", 0, |args| call(Builtin::All, args)),
+    ("all", 1, |args| call(Builtin::All, args)),
+    ("all", 2, |args| call(Builtin::All, args)),
+    ("flatten", 0, |args| call(Builtin::Flatten, args)),
+    ("flatten", 1, |args| call(Builtin::Flatten, args)),
+    ("range", 1, |args| call(Builtin::Range, args)),
+    ("range", 2, |args| call(Builtin::Range, args)),
+    ("range", 3, |args| call(Builtin::Range, args)),
+    ("has", 1, |args| call(Builtin::Has, args)),
+    ("in", 1, |args| call(Builtin::In, args)),
+    ("to_entries", 0, |args| call(Builtin::ToEntries, args)),
+    ("from_entries", 0, |args| call(Builtin::FromEntries, args)),
+    ("with_entries", 1, |args| call(Builtin::WithEntries, args)),
+    ("reverse", 0, |args| call(Builtin::Reverse, args)),
+    ("sort", 0, |args| call(Builtin::Sort, args)),
+    ("sort_by", 1, |args| call(Builtin::SortBy, args)),
+    ("group_by", 1, |args| call(Builtin::GroupBy, args)),
+    ("unique", 0, |args| call(Builtin::Unique, args)),
+    ("unique_by", 1, |args| call(Builtin::UniqueBy, args)),
+    ("min", 0, |args| call(Builtin::Min, args)),
+    ("max", 0, |args| call(Builtin::Max, args)),
+    ("min_by", 1, |args| call(Builtin::MinBy, args)),
+    ("max_by", 1, |args| call(Builtin::MaxBy, args)),
+    ("path", 1, |args| call(Builtin::Path, args)),
+    ("paths", 0, |args| call(Builtin::Paths, args)),
+    ("paths", 1, |args| call(Builtin::Paths, args)),
+    ("leaf_paths", 0, |args| call(Builtin::LeafPaths, args)),
+    ("getpath", 1, |args| call(Builtin::GetPath, args)),
+    ("setpath", 2, |args| call(Builtin::SetPath, args)),
+    ("delpaths", 1, |args| call(Builtin::DelPaths, args)),
+    ("del", 1, |args| call(Builtin::Del, args)),
+    ("pick", 1, |args| call(Builtin::Pick, args)),
+    ("test", 1, |args| call(Builtin::Test, args)),
+    ("test", 2, |args| call(Builtin::Test, args)),
+    ("match", 1, |args| call(Builtin::Match, args)),
+    ("match", 2, |args| call(Builtin::Match, args)),
+    ("capture", 1, |args| call(Builtin::Capture, args)),
+    ("capture", 2, |args| call(Builtin::Capture, args)),
+    ("scan", 1, |args| call(Builtin::Scan, args)),
+    ("scan", 2, |args| call(Builtin::Scan, args)),
+    ("splits", 1, |args| call(Builtin::Splits, args)),
+    ("splits", 2, |args| call(Builtin::Splits, args)),
+    ("split", 2, |args| call(Builtin::Split, args)),
+    ("sub", 2, |args| call(Builtin::Sub, args)),
+    ("sub", 3, |args| call(Builtin::Sub, args)),
+    ("gsub", 2, |args| call(Builtin::Gsub, args)),
+    ("gsub", 3, |args| call(Builtin::Gsub, args)),
+    ("builtins", 0, |args| call(Builtin::Builtins, args)),
+];
+
+fn call(builtin: Builtin, args: Vec<FilterExpression>) -> FilterExpression {
+    FilterExpression::Builtin(builtin, args.into_iter().map(Rc::new).collect())
+}
+
+/// Resolves a call of the builtin `name` with `args`, checking its arity.
+pub fn builtin(name: &str, args: Vec<FilterExpression>) -> Result<FilterExpression, Error> {
+    let arity = args.len();
+    if let Some((_, _, constructor)) = BUILTINS
+        .iter()
+        .find(|(builtin, params, _)| *builtin == name && *params == arity)
+    {
+        return Ok(constructor(args));
+    }
+    let arities: Vec<String> = BUILTINS
+        .iter()
+        .filter(|(builtin, _, _)| *builtin == name)
+        .map(|(builtin, params, _)| format!("{}/{}", builtin, params))
+        .collect();
+    if arities.is_empty() {
+        return Err(Error::Parser(format!(
+            "unknown function: {}/{}",
+            name, arity
+        )));
+    }
+    Err(Error::Parser(format!(
+        "{}/{} is not defined, expected {}",
+        name,
+        arity,
+        arities.join(" or ")
+    )))
+}
+
+/// Builtin evaluated natively, as opposed to one built from other expressions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Length,
+    Utf8ByteLength,
+    Type,
+    ToString,
+    ToNumber,
+    Add,
+    /// `any`, `any(cond)` or `any(generator; cond)`, stopping at the first truthy condition.
+    Any,
+    All,
+    Flatten,
+    /// `range(upto)`, `range(from; upto)` or `range(from; upto; by)`.
+    Range,
+    Has,
+    In,
+    ToEntries,
+    FromEntries,
+    WithEntries,
+    Reverse,
+    Sort,
+    /// `sort_by(f)`: sorts by the array of outputs of `f`, keeping equal members in order.
+    SortBy,
+    GroupBy,
+    Unique,
+    UniqueBy,
+    Min,
+    Max,
+    MinBy,
+    MaxBy,
+    /// `path(f)`: the paths within the input of the values `f` selects.
+    Path,
+    /// `paths` or `paths(f)`: paths to every nested value, or to those for which `f` is truthy.
+    Paths,
+    LeafPaths,
+    GetPath,
+    SetPath,
+    DelPaths,
+    Del,
+    /// `pick(f)`: only the parts of the input at the paths of `f`.
+    Pick,
+    /// `test(re; flags)` and the other regular expression builtins; see `Matcher` for the flags.
+    Test,
+    Match,
+    Capture,
+    /// `scan(re)`: every match, as if the `g` flag was given.
+    Scan,
+    Splits,
+    Split,
+    /// `sub(re; replacement; flags)`, where the replacement is applied to the named groups.
+    Sub,
+    Gsub,
+    Builtins,
+}
+
+impl Builtin {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Length => "length",
+            Builtin::Utf8ByteLength => "utf8bytelength",
+            Builtin::Type => "type",
+            Builtin::ToString => "tostring",
+            Builtin::ToNumber => "tonumber",
+            Builtin::Add => "add",
+            Builtin::Any => "any",
+            Builtin::All => "all",
+            Builtin::Flatten => "flatten",
+            Builtin::Range => "range",
+            Builtin::Has => "has",
+            Builtin::In => "in",
+            Builtin::ToEntries => "to_entries",
+            Builtin::FromEntries => "from_entries",
+            Builtin::WithEntries => "with_entries",
+            Builtin::Reverse => "reverse",
+            Builtin::Sort => "sort",
+            Builtin::SortBy => "sort_by",
+            Builtin::GroupBy => "group_by",
+            Builtin::Unique => "unique",
+            Builtin::UniqueBy => "unique_by",
+            Builtin::Min => "min",
+            Builtin::Max => "max",
+            Builtin::MinBy => "min_by",
+            Builtin::MaxBy => "max_by",
+            Builtin::Path => "path",
+            Builtin::Paths => "paths",
+            Builtin::LeafPaths => "leaf_paths",
+            Builtin::GetPath => "getpath",
+            Builtin::SetPath => "setpath",
+            Builtin::DelPaths => "delpaths",
+            Builtin::Del => "del",
+            Builtin::Pick => "pick",
+            Builtin::Test => "test",
+            Builtin::Match => "match",
+            Builtin::Capture => "capture",
+            Builtin::Scan => "scan",
+            Builtin::Splits => "splits",
+            Builtin::Split => "split",
+            Builtin::Sub => "sub",
+            Builtin::Gsub => "gsub",
+            Builtin::Builtins => "builtins",
+        }
+    }
+
+    /// Applies the builtin to `data`, evaluating its arguments in `env`.
+    pub fn eval(&self, args: &[Rc<FilterExpression>], data: Data, env: &Env) -> Outputs {
+        match self {
+            Builtin::Length => single(length(data)),
+            Builtin::Utf8ByteLength => single(match data {
+                Data::String(s) => Ok(Data::Integer(s.len() as i64)),
+                _ => Err(Error::Filter(format!(
+                    "{} only strings have UTF-8 byte length",
+                    describe(&data)
+                ))),
+            }),
+            Builtin::Type => single(Ok(Data::String(data.type_name().to_string()))),
+            Builtin::ToString => single(Ok(match data {
+                Data::String(s) => Data::String(s),
+                _ => Data::String(to_json(&data, JsonStyle::Compact)),
+            })),
+            Builtin::ToNumber => single(to_number(data)),
+            Builtin::Add => single(sum(data)),
+            Builtin::Any => single(quantify(false, args, data, env)),
+            Builtin::All => single(quantify(true, args, data, env)),
+            Builtin::Flatten => match args.first() {
+                None => single(flatten(data, &Data::Real(Real::new(f64::INFINITY)))),
+                Some(depth) => Box::new(
+                    depth
+                        .eval(data.clone(), env)
+                        .map(move |depth| flatten(data.clone(), &depth?)),
+                ),
+            },
+            Builtin::Range => Box::new(combinations(args, data, env).flat_map(
+                |bounds| match bounds {
+                    Ok(bounds) => range(bounds),
+                    Err(e) => single(Err(e)),
+                },
+            )),
+            Builtin::Has => Box::new(
+                args[0]
+                    .eval(data.clone(), env)
+                    .map(move |key| has(&data, &key?)),
+            ),
+            Builtin::In => Box::new(
+                args[0]
+                    .eval(data.clone(), env)
+                    .map(move |container| has(&container?, &data)),
+            ),
+            Builtin::ToEntries => single(to_entries(data)),
+            Builtin::FromEntries => single(from_entries(data)),
+            Builtin::WithEntries => single(with_entries(&args[0], data, env)),
+            Builtin::Reverse => single(match data {
+                Data::Null => Ok(Data::Array(Vec::new())),
+                Data::Array(mut arr) => {
+                    arr.reverse();
+                    Ok(Data::Array(arr))
+                }
+                Data::String(s) => Ok(Data::String(s.chars().rev().collect())),
+                _ => Err(Error::Filter(format!(
+                    "Cannot reverse {}",
+                    data.type_name()
+                ))),
+            }),
+            Builtin::Sort | Builtin::SortBy => {
+                single(sorted(args, data, env).map(|sorted| {
+                    Data::Array(sorted.into_iter().map(|(_, member)| member).collect())
+                }))
+            }
+            Builtin::GroupBy => single(
+                grouped(args, data, env)
+                    .map(|groups| Data::Array(groups.into_iter().map(Data::Array).collect())),
+            ),
+            Builtin::Unique | Builtin::UniqueBy => single(grouped(args, data, env).map(|groups| {
+                Data::Array(
+                    groups
+                        .into_iter()
+                        .filter_map(|g| g.into_iter().next())
+                        .collect(),
+                )
+            })),
+            Builtin::Min | Builtin::MinBy => single(extreme(Ordering::Less, args, data, env)),
+            Builtin::Max | Builtin::MaxBy => single(extreme(Ordering::Greater, args, data, env)),
+            Builtin::Path => Box::new(
+                args[0]
+                    .paths(Vec::new(), data, env)
+                    .map(|result| result.map(|(path, _)| Data::Array(path))),
+            ),
+            Builtin::Paths | Builtin::LeafPaths => {
+                let (condition, env) = (args.first().cloned(), env.clone());
+                let leaves = *self == Builtin::LeafPaths;
+                Box::new(
+                    FilterType::Recurse
+                        .paths(Vec::new(), data)
+                        .skip(1)
+                        .flat_map(move |result| {
+                            let (path, value) = match result {
+                                Ok(found) => found,
+                                Err(e) => return single(Err(e)),
+                            };
+                            let path = Data::Array(path);
+                            match &condition {
+                                Some(condition) => Box::new(
+                                    condition.eval(value, &env).filter_map(move |c| match c {
+                                        Ok(c) if c.is_truthy() => Some(Ok(path.clone())),
+                                        Ok(_) => None,
+                                        Err(e) => Some(Err(e)),
+                                    }),
+                                ),
+                                None if leaves
+                                    && matches!(value, Data::Array(_) | Data::Hash(_)) =>
+                                {
+                                    none()
+                                }
+                                None => single(Ok(path)),
+                            }
+                        }),
+                )
+            }
+            Builtin::GetPath => Box::new(
+                args[0]
+                    .eval(data.clone(), env)
+                    .map(move |path| getpath(&data, &as_path(path?)?)),
+            ),
+            Builtin::SetPath => {
+                Box::new(combinations(args, data.clone(), env).map(move |values| {
+                    let mut values = values?;
+                    let value = values.pop().unwrap_or(Data::Null);
+                    setpath(data.clone(), &as_path(values.remove(0))?, value)
+                }))
+            }
+            Builtin::DelPaths => {
+                Box::new(
+                    args[0]
+                        .eval(data.clone(), env)
+                        .map(move |paths| match paths? {
+                            Data::Array(paths) => delpaths(data.clone(), paths),
+                            _ => Err(Error::Filter(
+                                "Paths must be specified as an array".to_string(),
+                            )),
+                        }),
+                )
+            }
+            Builtin::Del => single(
+                args[0]
+                    .paths(Vec::new(), data.clone(), env)
+                    .map(|result| result.map(|(path, _)| Data::Array(path)))
+                    .collect::<Result<Vec<Data>, Error>>()
+                    .and_then(|paths| delpaths(data, paths)),
+            ),
+            Builtin::Pick => single(args[0].paths(Vec::new(), data.clone(), env).try_fold(
+                Data::Null,
+                |picked, result| {
+                    let (path, value) = result?;
+                    setpath(picked, &path, value)
+                },
+            )),
+            Builtin::Test
+            | Builtin::Match
+            | Builtin::Capture
+            | Builtin::Scan
+            | Builtin::Splits
+            | Builtin::Split => {
+                let builtin = *self;
+                Box::new(
+                    combinations(args, data.clone(), env).flat_map(move |values| {
+                        let outputs = values.and_then(|values| {
+                            let flags = values.get(1).unwrap_or(&Data::Null);
+                            let matcher = Matcher::new(&values[0], flags)?;
+                            match builtin {
+                                Builtin::Test => Ok(vec![matcher.test(&data)?]),
+                                Builtin::Match => matcher.matches(&data),
+                                Builtin::Capture => matcher.capture(&data),
+                                Builtin::Scan => matcher.global().scan(&data),
+                                Builtin::Splits => matcher.global().split(&data),
+                                _ => Ok(vec![Data::Array(matcher.global().split(&data)?)]),
+                            }
+                        });
+                        many(outputs)
+                    }),
+                )
+            }
+            Builtin::Sub | Builtin::Gsub => {
+                let global = *self == Builtin::Gsub;
+                let replacement = args[1].clone();
+                let mut patterns = vec![args[0].clone()];
+                patterns.extend(args.get(2).cloned());
+                let env = env.clone();
+                Box::new(
+                    combinations(&patterns, data.clone(), &env).flat_map(move |values| {
+                        let outputs = values.and_then(|values| {
+                            let flags = values.get(1).unwrap_or(&Data::Null);
+                            let mut matcher = Matcher::new(&values[0], flags)?;
+                            if global {
+                                matcher = matcher.global();
+                            }
+                            matcher.substitute(&data, &replacement, &env)
+                        });
+                        many(outputs)
+                    }),
+                )
+            }
+            Builtin::Builtins => single(Ok(Data::Array(
+                BUILTINS
+                    .iter()
+                    .map(|(name, arity, _)| Data::String(format!("{}/{}", name, arity)))
+                    .collect(),
+            ))),
+        }
+    }
+}
+
+fn many(outputs: Result<Vec<Data>, Error>) -> Outputs {
+    match outputs {
+        Ok(outputs) => Box::new(outputs.into_iter().map(Ok)),
+        Err(e) => single(Err(e)),
+    }
+}
+
+/// Every combination of the arguments' outputs, the first argument varying slowest.
+fn combinations(
+    args: &[Rc<FilterExpression>],
+    data: Data,
+    env: &Env,
+) -> Box<dyn Iterator<Item = Result<Vec<Data>, Error>>> {
+    let (first, rest) = match args.split_first() {
+        Some(split) => split,
+        None => return Box::new(std::iter::once(Ok(Vec::new()))),
+    };
+    let rest = rest.to_vec();
+    let env = env.clone();
+    Box::new(
+        first
+            .eval(data.clone(), &env)
+            .flat_map(move |value| match value {
+                Ok(value) => Box::new(combinations(&rest, data.clone(), &env).map(move |values| {
+                    let mut combination = vec![value.clone()];
+                    combination.extend(values?);
+                    Ok(combination)
+                }))
+                    as Box<dyn Iterator<Item = Result<Vec<Data>, Error>>>,
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            }),
+    )
+}
+
+fn length(data: Data) -> Result<Data, Error> {
+    let length = match data {
+        Data::Null => 0,
+        Data::Integer(n) => match n.checked_abs() {
+            Some(n) => n,
+            None => return Ok(Data::Real(Real::new((n as f64).abs()))),
+        },
+        Data::Real(n) => return Ok(Data::Real(Real::new(n.value().abs()))),
+        Data::String(s) => s.chars().count() as i64,
+        Data::Array(arr) => arr.len() as i64,
+        Data::Hash(map) => map.len() as i64,
+        Data::Boolean(_) => {
+            return Err(Error::Filter(format!("{} has no length", describe(&data))))
+        }
+    };
+    Ok(Data::Integer(length))
+}
+
+fn to_number(data: Data) -> Result<Data, Error> {
+    match data {
+        Data::Integer(_) | Data::Real(_) => Ok(data),
+        Data::String(s) => {
+            if let Ok(n) = s.parse::<i64>() {
+                return Ok(Data::Integer(n));
+            }
+            match s.parse::<f64>() {
+                Ok(n) if n.is_finite() => Ok(Data::Real(Real::with_literal(n, &s))),
+                _ => Err(Error::Filter(format!("Cannot parse '{}' as a number", s))),
+            }
+        }
+        _ => Err(Error::Filter(format!(
+            "{} cannot be parsed as a number",
+            describe(&data)
+        ))),
+    }
+}
+
+/// Members of an array or values of an object.
+fn members(data: Data) -> Result<Vec<Data>, Error> {
+    match data {
+        Data::Array(arr) => Ok(arr),
+        Data::Hash(map) => Ok(map.into_values().collect()),
+        _ => Err(Error::Filter(format!(
+            "Cannot iterate over {}",
+            data.type_name()
+        ))),
+    }
+}
+
+/// Adds up the members with `+`; nothing adds up to null.
+fn sum(data: Data) -> Result<Data, Error> {
+    if let Data::Null = data {
+        return Ok(Data::Null);
+    }
+    members(data)?
+        .into_iter()
+        .try_fold(Data::Null, |total, member| add(total, &member))
+}
+
+fn quantify(
+    all: bool,
+    args: &[Rc<FilterExpression>],
+    data: Data,
+    env: &Env,
+) -> Result<Data, Error> {
+    let candidates = match args {
+        [generator, _] => generator.eval(data, env),
+        _ => FilterType::Array.apply(data),
+    };
+    for candidate in candidates {
+        let candidate = candidate?;
+        let conditions = match args.last() {
+            Some(condition) => condition.eval(candidate, env),
+            None => single(Ok(candidate)),
+        };
+        for condition in conditions {
+            if condition?.is_truthy() != all {
+                return Ok(Data::Boolean(!all));
+            }
+        }
+    }
+    Ok(Data::Boolean(all))
+}
+
+fn flatten(data: Data, depth: &Data) -> Result<Data, Error> {
+    let depth = match depth {
+        Data::Integer(n) => *n as f64,
+        Data::Real(n) => n.value(),
+        _ => return Err(Error::Filter("flatten depth must be a number".to_string())),
+    };
+    if depth < 0.0 {
+        return Err(Error::Filter(
+            "flatten depth must not be negative".to_string(),
+        ));
+    }
+    match data {
+        Data::Array(arr) => {
+            let mut flat = Vec::new();
+            flatten_into(&mut flat, arr, depth);
+            Ok(Data::Array(flat))
+        }
+        _ => Err(Error::Filter(format!(
+            "Cannot flatten {}",
+            data.type_name()
+        ))),
+    }
+}
+
+fn flatten_into(flat: &mut Vec<Data>, members: Vec<Data>, depth: f64) {
+    for member in members {
+        match member {
+            Data::Array(nested) if depth > 0.0 => flatten_into(flat, nested, depth - 1.0),
+            _ => flat.push(member),
+        }
+    }
+}
+
+/// Numbers from `from` up to, but excluding, `upto`; a zero step outputs nothing.
+fn range(bounds: Vec<Data>) -> Outputs {
+    let (from, upto, by) = match &bounds[..] {
+        [upto] => (Data::Integer(0), upto.clone(), Data::Integer(1)),
+        [from, upto] => (from.clone(), upto.clone(), Data::Integer(1)),
+        [from, upto, by] => (from.clone(), upto.clone(), by.clone()),
+        _ => unreachable!("range takes 1 to 3 arguments"),
+    };
+    let number = |n: &Data| match n {
+        Data::Integer(n) => Some(*n as f64),
+        Data::Real(n) => Some(n.value()),
+        _ => None,
+    };
+    if let (Data::Integer(from), Data::Integer(upto), Data::Integer(by)) = (&from, &upto, &by) {
+        let (upto, by) = (*upto, *by);
+        return Box::new(
+            std::iter::successors(Some(*from), move |n| n.checked_add(by))
+                .take_while(move |n| (by > 0 && *n < upto) || (by < 0 && *n > upto))
+                .map(|n| Ok(Data::Integer(n))),
+        );
+    }
+    match (number(&from), number(&upto), number(&by)) {
+        (Some(from), Some(upto), Some(by)) => Box::new(
+            std::iter::successors(Some(from), move |n| Some(n + by))
+                .take_while(move |n| (by > 0.0 && *n < upto) || (by < 0.0 && *n > upto))
+                .map(|n| Ok(Data::Real(Real::new(n)))),
+        ),
+        _ => single(Err(Error::Filter(
+            "Range bounds must be numeric".to_string(),
+        ))),
+    }
+}
+
+/// Members of an array along with their sort keys: the array of outputs
+/// of the argument, if there is one, or else the member itself.
+fn keyed(args: &[Rc<FilterExpression>], data: Data, env: &Env) -> Result<Vec<(Data, Data)>, Error> {
+    let members = match data {
+        Data::Array(arr) => arr,
+        _ => {
+            return Err(Error::Filter(format!(
+                "{} cannot be sorted, as it is not an array",
+                describe(&data)
+            )))
+        }
+    };
+    members
+        .into_iter()
+        .map(|member| {
+            let key = match args.first() {
+                Some(f) => Data::Array(f.eval(member.clone(), env).collect::<Result<_, _>>()?),
+                None => member.clone(),
+            };
+            Ok((key, member))
+        })
+        .collect()
+}
+
+fn sorted(
+    args: &[Rc<FilterExpression>],
+    data: Data,
+    env: &Env,
+) -> Result<Vec<(Data, Data)>, Error> {
+    let mut members = keyed(args, data, env)?;
+    members.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(members)
+}
+
+/// Sorted members, grouped by equal keys.
+fn grouped(args: &[Rc<FilterExpression>], data: Data, env: &Env) -> Result<Vec<Vec<Data>>, Error> {
+    let mut groups: Vec<(Data, Vec<Data>)> = Vec::new();
+    for (key, member) in sorted(args, data, env)? {
+        match groups.last_mut() {
+            Some((last, group)) if *last == key => group.push(member),
+            _ => groups.push((key, vec![member])),
+        }
+    }
+    Ok(groups.into_iter().map(|(_, group)| group).collect())
+}
+
+/// The first smallest or the last largest member, or null if there are none.
+fn extreme(
+    wanted: Ordering,
+    args: &[Rc<FilterExpression>],
+    data: Data,
+    env: &Env,
+) -> Result<Data, Error> {
+    let mut extreme: Option<(Data, Data)> = None;
+    for (key, member) in keyed(args, data, env)? {
+        let replace = match &extreme {
+            Some((best, _)) => {
+                key.cmp(best) == wanted || (wanted == Ordering::Greater && key == *best)
+            }
+            None => true,
+        };
+        if replace {
+            extreme = Some((key, member));
+        }
+    }
+    Ok(extreme.map_or(Data::Null, |(_, member)| member))
+}
+
+fn has(data: &Data, key: &Data) -> Result<Data, Error> {
+    let found = match (data, key) {
+        (Data::Hash(map), Data::String(name)) => map.contains_key(name),
+        (Data::Array(arr), Data::Integer(idx)) => *idx >= 0 && (*idx as usize) < arr.len(),
+        (Data::Array(arr), Data::Real(idx)) => idx.value() >= 0.0 && idx.value() < arr.len() as f64,
+        _ => {
+            return Err(Error::Filter(format!(
+                "Cannot check whether {} has a {} key",
+                data.type_name(),
+                key.type_name()
+            )))
+        }
+    };
+    Ok(Data::Boolean(found))
+}
+
+fn entry(key: Data, value: Data) -> Data {
+    let mut entry = IndexMap::new();
+    entry.insert("key".to_string(), key);
+    entry.insert("value".to_string(), value);
+    Data::Hash(entry)
+}
+
+fn to_entries(data: Data) -> Result<Data, Error> {
+    match data {
+        Data::Hash(map) => Ok(Data::Array(
+            map.into_iter()
+                .map(|(key, value)| entry(Data::String(key), value))
+                .collect(),
+        )),
+        Data::Array(arr) => Ok(Data::Array(
+            arr.into_iter()
+                .enumerate()
+                .map(|(idx, value)| entry(Data::Integer(idx as i64), value))
+                .collect(),
+        )),
+        _ => Err(Error::Filter(format!("{} has no keys", data.type_name()))),
+    }
+}
+
+/// Builds an object from `{key, value}` entries, also accepting the
+/// `k`, `name`, `Name`, `K` and `Key` names for keys and `v` for values.
+fn from_entries(data: Data) -> Result<Data, Error> {
+    let mut object = IndexMap::new();
+    for member in members(data)? {
+        let mut entry = match member {
+            Data::Hash(entry) => entry,
+            _ => {
+                return Err(Error::Filter(format!(
+                    "Cannot index {} with \"key\"",
+                    member.type_name()
+                )))
+            }
+        };
+        let key = ["key", "k", "name", "Name", "K", "Key"]
+            .iter()
+            .filter_map(|name| entry.get(*name))
+            .find(|key| key.is_truthy())
+            .cloned()
+            .unwrap_or(Data::Null);
+        let key = match key {
+            Data::String(key) => key,
+            _ => to_json(&key, JsonStyle::Compact),
+        };
+        let value = match entry.swap_remove("value") {
+            Some(value) => value,
+            None => entry.swap_remove("v").unwrap_or(Data::Null),
+        };
+        object.insert(key, value);
+    }
+    Ok(Data::Hash(object))
+}
+
+fn with_entries(f: &FilterExpression, data: Data, env: &Env) -> Result<Data, Error> {
+    let mut mapped = Vec::new();
+    for entry in members(to_entries(data)?)? {
+        for output in f.eval(entry, env) {
+            mapped.push(output?);
+        }
+    }
+    from_entries(Data::Array(mapped))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checks_arity() {
+        assert!(builtin("length", Vec::new()).is_ok());
+        assert!(matches!(
+            builtin("length", vec![FilterExpression::Literal(Data::Null)]),
+            Err(Error::Parser(msg)) if msg == "length/1 is not defined, expected length/0"
+        ));
+        assert!(matches!(
+            builtin("nope", Vec::new()),
+            Err(Error::Parser(msg)) if msg == "unknown function: nope/0"
+        ));
+    }
+
+    #[test]
+    fn describes_values() {
+        let input = r#"[null, true, -2, "héllo", [1, 2], {"a": 1}]"#;
+        assert_eq!(
+            run(".[] | type", input),
+            vec![
+                r#""null""#,
+                r#""boolean""#,
+                r#""number""#,
+                r#""string""#,
+                r#""array""#,
+                r#""object""#
+            ]
+        );
+        assert_eq!(run(".[2:] | .[] | length", input), vec!["2", "5", "2", "1"]);
+        assert_eq!(run(".[3] | utf8bytelength", input), vec!["6"]);
+        assert_eq!(
+            run("try (.[1] | length) catch .", input),
+            vec![r#""boolean (true) has no length""#]
+        );
+        assert_eq!(
+            run(".[] | tostring", "[1, \"a\", [true]]"),
+            vec![r#""1""#, r#""a""#, r#""[true]""#]
+        );
+        assert_eq!(
+            run(".[] | tonumber", r#"[1, "2", "2.5"]"#),
+            vec!["1", "2", "2.5"]
+        );
+        assert_eq!(
+            run("try tonumber catch .", r#""x""#),
+            vec![r#""Cannot parse 'x' as a number""#]
+        );
+    }
+
+    #[test]
+    fn aggregates_members() {
+        assert_eq!(run("add", "[1, 2, 3]"), vec!["6"]);
+        assert_eq!(run("add", r#"["a", "b"]"#), vec![r#""ab""#]);
+        assert_eq!(run("add", "[]"), vec!["null"]);
+        assert_eq!(run("any, all", "[true, false]"), vec!["true", "false"]);
+        assert_eq!(run("any, all", "[]"), vec!["false", "true"]);
+        assert_eq!(
+            run("any(. > 2), all(. > 0)", "[1, 2, 3]"),
+            vec!["true", "true"]
+        );
+        assert_eq!(run("any(.[]; . == 2)", "[1, 2, 3]"), vec!["true"]);
+        assert_eq!(run("any(true, error(\"x\"); .)", "null"), vec!["true"]);
+        assert_eq!(
+            run("flatten, flatten(1)", "[1, [2, [3]]]"),
+            vec!["[1,2,3]", "[1,2,[3]]"]
+        );
+        assert_eq!(run("reverse", "[1, 2, 3]"), vec!["[3,2,1]"]);
+        assert_eq!(run("reverse", r#""abc""#), vec![r#""cba""#]);
+    }
+
+    #[test]
+    fn generates_ranges() {
+        assert_eq!(run("[range(3)]", "null"), vec!["[0,1,2]"]);
+        assert_eq!(run("[range(2; 4)]", "null"), vec!["[2,3]"]);
+        assert_eq!(run("[range(0; 10; 3)]", "null"), vec!["[0,3,6,9]"]);
+        assert_eq!(run("[range(5; 0; -2)]", "null"), vec!["[5,3,1]"]);
+        assert_eq!(run("[range(0; 1; 0.5)]", "null"), vec!["[0,0.5]"]);
+        assert_eq!(
+            run("[range(0, 1; 2, 3)]", "null"),
+            vec!["[0,1,0,1,2,1,1,2]"]
+        );
+    }
+
+    #[test]
+    fn checks_keys() {
+        let input = r#"{"a": 1}"#;
+        assert_eq!(run(r#"has("a"), has("b")"#, input), vec!["true", "false"]);
+        assert_eq!(run("has(0), has(2)", "[1, 2]"), vec!["true", "false"]);
+        assert_eq!(run(r#""a" | in({"a": 1})"#, "null"), vec!["true"]);
+        assert!(crate::parser::ExpressionParser::new("has(\"a\")")
+            .parse()
+            .unwrap()
+            .apply(Data::Array(Vec::new()))
+            .any(|r| r.is_err()));
+    }
+
+    #[test]
+    fn converts_entries() {
+        let input = r#"{"a": 1, "b": 2}"#;
+        assert_eq!(
+            run("to_entries", input),
+            vec![r#"[{"key":"a","value":1},{"key":"b","value":2}]"#]
+        );
+        assert_eq!(
+            run("to_entries | from_entries", input),
+            vec![r#"{"a":1,"b":2}"#]
+        );
+        assert_eq!(
+            run(
+                "from_entries",
+                r#"[{"k": "x", "v": 1}, {"name": 2}, {"key": false, "Key": "y"}]"#
+            ),
+            vec![r#"{"x":1,"2":null,"y":null}"#]
+        );
+        assert_eq!(
+            run(
+                r#"with_entries({key: .key, value: (.value + 1)} | select(.key != "b"))"#,
+                input
+            ),
+            vec![r#"{"a":2}"#]
+        );
+    }
+
+    #[test]
+    fn sorts_members() {
+        let input = r#"[{"n": "b", "c": 2}, {"n": "a", "c": 1}, {"n": "c", "c": 2}]"#;
+        assert_eq!(
+            run("sort", r#"[3, "a", null, [1], 1.5, {"a": 1}, true]"#),
+            vec![r#"[null,true,1.5,3,"a",[1],{"a":1}]"#]
+        );
+        assert_eq!(
+            run("sort_by(.c) | map(.n)", input),
+            vec![r#"["a","b","c"]"#]
+        );
+        assert_eq!(
+            run(
+                "sort_by(.c, .n) | map(.n)",
+                r#"[{"c": 1, "n": "z"}, {"c": 1, "n": "y"}]"#
+            ),
+            vec![r#"["y","z"]"#]
+        );
+        assert_eq!(
+            run("group_by(.c) | map(map(.n))", input),
+            vec![r#"[["a"],["b","c"]]"#]
+        );
+        assert_eq!(run("unique", "[2, 1, 2.0, 1]"), vec!["[1,2]"]);
+        assert_eq!(run("unique_by(.c) | map(.n)", input), vec![r#"["a","b"]"#]);
+        assert_eq!(
+            run("try sort catch .", "{}"),
+            vec![r#""object ({}) cannot be sorted, as it is not an array""#]
+        );
+    }
+
+    #[test]
+    fn finds_extremes() {
+        let input = r#"[{"n": "b", "c": 2}, {"n": "a", "c": 1}, {"n": "c", "c": 2}]"#;
+        assert_eq!(run("min, max", "[3, 1, 2]"), vec!["1", "3"]);
+        assert_eq!(run("min, max", "[]"), vec!["null", "null"]);
+        assert_eq!(
+            run("min_by(.c).n, max_by(.c).n", input),
+            vec![r#""a""#, r#""c""#]
+        );
+    }
+
+    #[test]
+    fn lists_builtins() {
+        let names = run("builtins[]", "null");
+        assert!(names.contains(&r#""length/0""#.to_string()));
+        assert!(names.contains(&r#""range/3""#.to_string()));
+    }
+}
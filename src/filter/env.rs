@@ -0,0 +1,116 @@
+use super::*;
+use std::rc::Rc;
+
+/// Lexical scope of a filter: the variables and functions visible at a point of evaluation.
+///
+/// Scopes are immutable linked lists, so binding a name is cheap and
+/// never affects the enclosing scope.
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    head: Option<Rc<Scope>>,
+}
+
+#[derive(Debug)]
+struct Scope {
+    binding: Binding,
+    parent: Env,
+}
+
+#[derive(Debug)]
+enum Binding {
+    Variable(String, Data),
+    Function(Rc<Function>),
+    /// Filter argument of a function call, evaluated in the caller's scope.
+    Closure(String, Rc<FilterExpression>, Env),
+}
+
+/// Function found in scope, along with the scope its body is evaluated in.
+pub enum Callable {
+    Function(Rc<Function>, Env),
+    Closure(Rc<FilterExpression>, Env),
+}
+
+impl Env {
+    fn push(&self, binding: Binding) -> Env {
+        Env {
+            head: Some(Rc::new(Scope {
+                binding,
+                parent: self.clone(),
+            })),
+        }
+    }
+
+    /// Returns a new scope with `name` bound to `value`, shadowing any outer binding.
+    pub fn bind(&self, name: &str, value: Data) -> Env {
+        self.push(Binding::Variable(name.to_string(), value))
+    }
+
+    /// Returns a new scope where `function` is defined, including within its own body.
+    pub fn define(&self, function: Rc<Function>) -> Env {
+        self.push(Binding::Function(function))
+    }
+
+    /// Returns a new scope where calling `name` evaluates `body` in the `closure` scope.
+    pub fn bind_closure(&self, name: &str, body: Rc<FilterExpression>, closure: Env) -> Env {
+        self.push(Binding::Closure(name.to_string(), body, closure))
+    }
+
+    pub fn variable(&self, name: &str) -> Result<Data, Error> {
+        let mut env = self;
+        while let Some(scope) = &env.head {
+            if let Binding::Variable(bound, value) = &scope.binding {
+                if bound == name {
+                    return Ok(value.clone());
+                }
+            }
+            env = &scope.parent;
+        }
+        Err(Error::Filter(format!("${} is not defined", name)))
+    }
+
+    pub fn function(&self, name: &str, arity: usize) -> Result<Callable, Error> {
+        let mut env = self;
+        while let Some(scope) = &env.head {
+            match &scope.binding {
+                Binding::Function(function)
+                    if function.name == name && function.params.len() == arity =>
+                {
+                    return Ok(Callable::Function(function.clone(), env.clone()));
+                }
+                Binding::Closure(bound, body, closure) if bound == name && arity == 0 => {
+                    return Ok(Callable::Closure(body.clone(), closure.clone()));
+                }
+                _ => env = &scope.parent,
+            }
+        }
+        Err(Error::Filter(format!("{}/{} is not defined", name, arity)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inner_bindings_shadow_outer_ones() {
+        let outer = Env::default().bind("a", Data::Integer(1));
+        let inner = outer.bind("a", Data::Integer(2));
+        assert!(matches!(inner.variable("a"), Ok(Data::Integer(2))));
+        assert!(matches!(outer.variable("a"), Ok(Data::Integer(1))));
+        assert!(outer.variable("b").is_err(), "should not be defined");
+    }
+
+    #[test]
+    fn functions_are_resolved_by_arity() {
+        let body = Rc::new(FilterExpression::Literal(Data::Null));
+        let env = Env::default().define(Rc::new(Function {
+            name: "f".to_string(),
+            params: vec!["a".to_string()],
+            body: body.clone(),
+        }));
+        let env = env.bind_closure("g", body, Env::default());
+        assert!(matches!(env.function("f", 1), Ok(Callable::Function(_, _))));
+        assert!(matches!(env.function("g", 0), Ok(Callable::Closure(_, _))));
+        assert!(env.function("f", 0).is_err(), "should not be defined");
+    }
+}
@@ -1,61 +1,1636 @@
 use super::*;
+use crate::output::*;
+use indexmap::IndexMap;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 
-#[derive(Debug)]
-pub struct FilterExpression {
-    groups: Vec<FilterGroup>,
+/// Parsed filter expression tree.
+#[derive(Debug, Clone)]
+pub enum FilterExpression {
+    Filter(FilterType),
+    Literal(Data),
+    Variable(String),
+    Negate(Rc<FilterExpression>),
+    /// Juxtaposed filters, such as `.[]keys`; each is applied to the outputs of the previous one.
+    Chain(Rc<FilterExpression>, Rc<FilterExpression>),
+    Binary(Operator, Rc<FilterExpression>, Rc<FilterExpression>),
+    /// `select(f)`: passes the input through once for every truthy output of `f`.
+    Select(Rc<FilterExpression>),
+    /// `map(f)`: collects the outputs of `f` for every member into a new array.
+    Map(Rc<FilterExpression>),
+    /// `map_values(f)`: replaces every member with the first output of `f`, dropping it if there is none.
+    MapValues(Rc<FilterExpression>),
+    /// `if cond then a else b end`; `elif` branches are nested in the `else` branch,
+    /// and a missing `else` passes the input through.
+    If(
+        Rc<FilterExpression>,
+        Rc<FilterExpression>,
+        Option<Rc<FilterExpression>>,
+    ),
+    /// `source as $x | body`: evaluates the body for every output of the source, with the
+    /// pattern variables bound; `?//` separates alternative patterns.
+    Bind(Rc<FilterExpression>, Vec<Pattern>, Rc<FilterExpression>),
+    /// `def f: body; rest`: evaluates the rest with the function in scope.
+    Define(Rc<Function>, Rc<FilterExpression>),
+    /// `reduce source as $x (init; update)`: folds every source output into the state.
+    Reduce(
+        Rc<FilterExpression>,
+        Vec<Pattern>,
+        Rc<FilterExpression>,
+        Rc<FilterExpression>,
+    ),
+    /// `foreach source as $x (init; update; extract)`: like `reduce`, but outputs
+    /// every intermediate state, passed through `extract` if there is one.
+    Foreach(
+        Rc<FilterExpression>,
+        Vec<Pattern>,
+        Rc<FilterExpression>,
+        Rc<FilterExpression>,
+        Option<Rc<FilterExpression>>,
+    ),
+    /// `[expr]`: collects every output of the expression into an array.
+    Collect(Rc<FilterExpression>),
+    /// `target[key]` with a computed key, evaluated against the input of the whole term.
+    Index(Rc<FilterExpression>, Rc<FilterExpression>),
+    /// `target[from:to]` with computed bounds; missing bounds are open.
+    Slice(
+        Rc<FilterExpression>,
+        Option<Rc<FilterExpression>>,
+        Option<Rc<FilterExpression>>,
+    ),
+    /// `{key: value, ...}`: one object for every combination of key and value outputs.
+    Object(Vec<(Rc<FilterExpression>, Rc<FilterExpression>)>),
+    /// `"text \(expr)"`: one string for every combination of interpolated outputs,
+    /// which are passed through the `@format` written before the string, if any.
+    Template(Option<String>, Vec<TemplatePart>),
+    /// Call of a user-defined function or of a function parameter.
+    Call(String, Vec<Rc<FilterExpression>>),
+    /// Call of a builtin that is evaluated natively.
+    Builtin(Builtin, Vec<Rc<FilterExpression>>),
+    /// `recurse(f; cond)`: the input, then recursively every output of `f`
+    /// for which `cond` is truthy, if given.
+    Recurse(Rc<FilterExpression>, Option<Rc<FilterExpression>>),
+    /// `try body catch handler`: outputs of the body up to its first error, which is
+    /// passed to the handler, if any, instead of aborting the query. `body?` has no handler.
+    Try(Rc<FilterExpression>, Option<Rc<FilterExpression>>),
 }
 
-impl Default for FilterExpression {
-    fn default() -> Self {
-        let g: FilterGroup = Default::default();
-        Self { groups: vec![g] }
+#[derive(Debug, Clone)]
+pub enum TemplatePart {
+    Literal(String),
+    Expression(Rc<FilterExpression>),
+}
+
+impl FilterExpression {
+    pub fn binary(op: Operator, lhs: FilterExpression, rhs: FilterExpression) -> Self {
+        FilterExpression::Binary(op, Rc::new(lhs), Rc::new(rhs))
+    }
+
+    pub fn chain(lhs: FilterExpression, rhs: FilterExpression) -> Self {
+        FilterExpression::Chain(Rc::new(lhs), Rc::new(rhs))
+    }
+
+    /// Binding strength of the expression, used to decide where parentheses go.
+    fn precedence(&self) -> u8 {
+        match self {
+            FilterExpression::Binary(op, _, _) => op.precedence(),
+            // The body of a binding extends as far right as possible.
+            FilterExpression::Bind(_, _, _) | FilterExpression::Define(_, _) => 0,
+            // The handler would otherwise take in whatever follows it.
+            FilterExpression::Try(_, Some(_)) => 0,
+            _ => u8::MAX,
+        }
+    }
+
+    fn fmt_operand(
+        &self,
+        f: &mut std::fmt::Formatter,
+        operand: &FilterExpression,
+        strict: bool,
+    ) -> Result<(), std::fmt::Error> {
+        let precedence = self.precedence();
+        let operand_precedence = operand.precedence();
+        if operand_precedence < precedence || (strict && operand_precedence == precedence) {
+            write!(f, "({})", operand)
+        } else {
+            write!(f, "{}", operand)
+        }
+    }
+}
+
+impl FilterExpression {
+    /// Formats the term that brackets are applied to, leaving out `.` before them.
+    fn fmt_target(
+        &self,
+        f: &mut std::fmt::Formatter,
+        target: &FilterExpression,
+    ) -> Result<(), std::fmt::Error> {
+        match target {
+            FilterExpression::Filter(FilterType::Current) => write!(f, "."),
+            _ => self.fmt_operand(f, target, false),
+        }
+    }
+
+    /// Formats an operand of `try` or `?`, which only take in a single term.
+    fn fmt_postfix(
+        f: &mut std::fmt::Formatter,
+        operand: &FilterExpression,
+    ) -> Result<(), std::fmt::Error> {
+        if operand.precedence() < u8::MAX {
+            write!(f, "({})", operand)
+        } else {
+            write!(f, "{}", operand)
+        }
+    }
+
+    /// Formats the `source as $x ?// $y` part of bindings and folds.
+    fn fmt_source(
+        f: &mut std::fmt::Formatter,
+        source: &FilterExpression,
+        patterns: &[Pattern],
+    ) -> Result<(), std::fmt::Error> {
+        if source.precedence() < u8::MAX {
+            write!(f, "({})", source)?;
+        } else {
+            write!(f, "{}", source)?;
+        }
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        write!(f, " as {}", patterns.join(" ?// "))
     }
 }
 
 impl std::fmt::Display for FilterExpression {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        for (idx, group) in self.groups.iter().enumerate() {
-            if idx > 0 {
-                write!(f, "{}", &group.strategy)?;
+        match self {
+            FilterExpression::Filter(t) => write!(f, "{}", t),
+            FilterExpression::Literal(data) => write!(f, "{}", to_json(data, JsonStyle::Compact)),
+            FilterExpression::Variable(name) => write!(f, "${}", name),
+            FilterExpression::Negate(operand) => {
+                write!(f, "-")?;
+                self.fmt_operand(f, operand, false)
+            }
+            FilterExpression::Chain(lhs, rhs) => {
+                self.fmt_operand(f, lhs, false)?;
+                match rhs.as_ref() {
+                    // Brackets directly follow the previous term, as in `.a[0]`.
+                    FilterExpression::Filter(t) if t.is_bracketed() => {
+                        write!(f, "{}", &t.to_string()[1..])
+                    }
+                    _ => self.fmt_operand(f, rhs, false),
+                }
+            }
+            FilterExpression::Binary(op, lhs, rhs) => {
+                let right = op.is_right_associative();
+                self.fmt_operand(f, lhs, right)?;
+                match op {
+                    Operator::And | Operator::Or => write!(f, " {} ", op)?,
+                    _ => write!(f, "{}", op)?,
+                }
+                self.fmt_operand(f, rhs, !right)
+            }
+            FilterExpression::Select(arg) => write!(f, "select({})", arg),
+            FilterExpression::Map(arg) => write!(f, "map({})", arg),
+            FilterExpression::MapValues(arg) => write!(f, "map_values({})", arg),
+            FilterExpression::If(condition, then, otherwise) => {
+                write!(f, "if {} then {}", condition, then)?;
+                let mut otherwise = otherwise;
+                while let Some(branch) = otherwise {
+                    match branch.as_ref() {
+                        FilterExpression::If(condition, then, rest) => {
+                            write!(f, " elif {} then {}", condition, then)?;
+                            otherwise = rest;
+                        }
+                        _ => {
+                            write!(f, " else {}", branch)?;
+                            break;
+                        }
+                    }
+                }
+                write!(f, " end")
+            }
+            FilterExpression::Bind(source, patterns, body) => {
+                FilterExpression::fmt_source(f, source, patterns)?;
+                write!(f, "|{}", body)
+            }
+            FilterExpression::Define(function, rest) => write!(f, "{} {}", function, rest),
+            FilterExpression::Reduce(source, patterns, init, update) => {
+                write!(f, "reduce ")?;
+                FilterExpression::fmt_source(f, source, patterns)?;
+                write!(f, " ({};{})", init, update)
+            }
+            FilterExpression::Foreach(source, patterns, init, update, extract) => {
+                write!(f, "foreach ")?;
+                FilterExpression::fmt_source(f, source, patterns)?;
+                match extract {
+                    Some(extract) => write!(f, " ({};{};{})", init, update, extract),
+                    None => write!(f, " ({};{})", init, update),
+                }
+            }
+            FilterExpression::Collect(members) => write!(f, "[{}]", members),
+            FilterExpression::Index(target, key) => {
+                self.fmt_target(f, target)?;
+                write!(f, "[{}]", key)
+            }
+            FilterExpression::Slice(target, from, to) => {
+                self.fmt_target(f, target)?;
+                write!(f, "[")?;
+                if let Some(from) = from {
+                    write!(f, "{}", from)?;
+                }
+                write!(f, ":")?;
+                if let Some(to) = to {
+                    write!(f, "{}", to)?;
+                }
+                write!(f, "]")
+            }
+            FilterExpression::Object(entries) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+                    match key.as_ref() {
+                        FilterExpression::Literal(Data::String(name)) if is_identifier(name) => {
+                            write!(f, "{}", name)?
+                        }
+                        FilterExpression::Literal(Data::String(_)) => write!(f, "{}", key)?,
+                        _ => write!(f, "({})", key)?,
+                    }
+                    if value.precedence() <= Operator::Comma.precedence() {
+                        write!(f, ":({})", value)?;
+                    } else {
+                        write!(f, ":{}", value)?;
+                    }
+                }
+                write!(f, "}}")
+            }
+            FilterExpression::Template(format, parts) => {
+                if let Some(format) = format {
+                    write!(f, "@{} ", format)?;
+                }
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        TemplatePart::Literal(string) => {
+                            let mut quoted = String::new();
+                            write_json_string(&mut quoted, string);
+                            write!(f, "{}", &quoted[1..quoted.len() - 1])?;
+                        }
+                        TemplatePart::Expression(expr) => write!(f, "\\({})", expr)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            FilterExpression::Builtin(builtin, args) if args.is_empty() => {
+                write!(f, "{}", builtin.name())
+            }
+            FilterExpression::Builtin(builtin, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", builtin.name(), args.join(";"))
+            }
+            FilterExpression::Recurse(step, None) => write!(f, "recurse({})", step),
+            FilterExpression::Recurse(step, Some(condition)) => {
+                write!(f, "recurse({};{})", step, condition)
+            }
+            FilterExpression::Try(body, None) => {
+                FilterExpression::fmt_postfix(f, body)?;
+                write!(f, "?")
+            }
+            FilterExpression::Try(body, Some(handler)) => {
+                write!(f, "try ")?;
+                FilterExpression::fmt_postfix(f, body)?;
+                write!(f, " catch ")?;
+                FilterExpression::fmt_postfix(f, handler)
+            }
+            FilterExpression::Call(name, args) => {
+                write!(f, "{}", name)?;
+                if !args.is_empty() {
+                    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                    write!(f, "({})", args.join(";"))?;
+                }
+                Ok(())
             }
-            write!(f, "{}", &group)?;
         }
-        Ok(())
     }
 }
 
-impl FilterExpression {
-    pub fn change_strategy(&mut self, s: DataStrategy) {
-        if let Some(last) = self.groups.last_mut() {
-            last.strategy = s;
+/// Feeds every output of `lhs` into `rhs`.
+fn pipe(lhs: &FilterExpression, rhs: Rc<FilterExpression>, data: Data, env: &Env) -> Outputs {
+    let env = env.clone();
+    Box::new(lhs.eval(data, &env).flat_map(move |result| match result {
+        Ok(data) => rhs.eval(data, &env),
+        Err(e) => single(Err(e)),
+    }))
+}
+
+/// Applies `op` to every combination of `lhs` and `rhs` outputs,
+/// iterating over the right-hand side outputs first like jq does.
+fn combine(
+    op: Operator,
+    lhs: Rc<FilterExpression>,
+    rhs: &FilterExpression,
+    data: Data,
+    env: &Env,
+) -> Outputs {
+    let env = env.clone();
+    Box::new(rhs.eval(data.clone(), &env).flat_map(move |result| {
+        match result {
+            Ok(rhs) => Box::new(
+                lhs.eval(data.clone(), &env)
+                    .map(move |lhs| op.compute(lhs?, &rhs)),
+            ) as Outputs,
+            Err(e) => single(Err(e)),
         }
+    }))
+}
+
+/// Short-circuiting `and`/`or`: `rhs` is only evaluated when `lhs` doesn't decide the result.
+fn logical(
+    op: Operator,
+    lhs: &FilterExpression,
+    rhs: Rc<FilterExpression>,
+    data: Data,
+    env: &Env,
+) -> Outputs {
+    let decisive = op == Operator::Or;
+    let env = env.clone();
+    Box::new(lhs.eval(data.clone(), &env).flat_map(move |result| {
+        match result {
+            Ok(lhs) if lhs.is_truthy() == decisive => single(Ok(Data::Boolean(decisive))),
+            Ok(_) => Box::new(
+                rhs.eval(data.clone(), &env)
+                    .map(|rhs| Ok(Data::Boolean(rhs?.is_truthy()))),
+            ) as Outputs,
+            Err(e) => single(Err(e)),
+        }
+    }))
+}
+
+/// Truthy outputs of `lhs`, ignoring its errors, or the outputs of `rhs` if there are none.
+fn alternative(
+    lhs: &FilterExpression,
+    rhs: Rc<FilterExpression>,
+    data: Data,
+    env: &Env,
+) -> Outputs {
+    let found = Rc::new(Cell::new(false));
+    let seen = found.clone();
+    let truthy = lhs
+        .eval(data.clone(), env)
+        .filter_map(move |result| match result {
+            Ok(value) if value.is_truthy() => {
+                seen.set(true);
+                Some(Ok(value))
+            }
+            _ => None,
+        });
+    let env = env.clone();
+    Box::new(truthy.chain(lazy(move || {
+        if found.get() {
+            none()
+        } else {
+            rhs.eval(data, &env)
+        }
+    })))
+}
+
+/// Applies an assignment operator, outputting the whole input with the values at the
+/// paths of `lhs` changed; `rhs` is evaluated against the input, once for every output
+/// but with `|=`, where it's applied to each value being replaced.
+fn assign(
+    op: Operator,
+    lhs: Rc<FilterExpression>,
+    rhs: Rc<FilterExpression>,
+    data: Data,
+    env: &Env,
+) -> Outputs {
+    let env = env.clone();
+    if op == Operator::Update {
+        return single(update(&lhs, data, &env, |value| {
+            rhs.eval(value, &env).next().transpose()
+        }));
     }
+    Box::new(rhs.eval(data.clone(), &env).map(move |value| {
+        let value = value?;
+        update(&lhs, data.clone(), &env, |current| {
+            let updated = match op.assigned() {
+                None => value.clone(),
+                Some(Operator::Alternative) if current.is_truthy() => current,
+                Some(Operator::Alternative) => value.clone(),
+                Some(op) => op.compute(current, &value)?,
+            };
+            Ok(Some(updated))
+        })
+    }))
+}
 
-    pub fn add_group(&mut self, g: FilterGroup) {
-        self.groups.push(g);
+/// Replaces the values at the paths of `lhs` within `data` with the result of `f`,
+/// deleting those for which it has none.
+fn update<F>(lhs: &FilterExpression, data: Data, env: &Env, f: F) -> Result<Data, Error>
+where
+    F: Fn(Data) -> Result<Option<Data>, Error>,
+{
+    let paths = lhs
+        .paths(Vec::new(), data.clone(), env)
+        .map(|result| result.map(|(path, _)| path))
+        .collect::<Result<Vec<Vec<Data>>, Error>>()?;
+    let mut data = data;
+    let mut deleted = Vec::new();
+    for path in paths {
+        match f(getpath(&data, &path)?)? {
+            Some(value) => data = setpath(data, &path, value)?,
+            None => deleted.push(Data::Array(path)),
+        }
     }
+    delpaths(data, deleted)
+}
 
-    pub fn add_set(&mut self, s: FilterSet) {
-        let l = self.groups.len();
-        self.groups[l - 1].add_set(s);
+fn select(condition: &FilterExpression, data: Data, env: &Env) -> Outputs {
+    Box::new(
+        condition
+            .eval(data.clone(), env)
+            .filter_map(move |result| match result {
+                Ok(value) if value.is_truthy() => Some(Ok(data.clone())),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }),
+    )
+}
+
+fn map(f: &FilterExpression, data: Data, env: &Env) -> Result<Data, Error> {
+    let members = match data {
+        Data::Array(arr) => arr,
+        Data::Hash(map) => map.into_values().collect(),
+        _ => {
+            return Err(Error::Filter(format!(
+                "Cannot iterate over {}",
+                data.type_name()
+            )))
+        }
+    };
+    let mut results = Vec::new();
+    for member in members {
+        for result in f.eval(member, env) {
+            results.push(result?);
+        }
     }
+    Ok(Data::Array(results))
+}
 
-    pub fn add_filter(&mut self, t: FilterType) {
-        if self.groups.is_empty() {
-            let g: FilterGroup = Default::default();
-            self.groups.push(g);
+fn map_values(f: &FilterExpression, data: Data, env: &Env) -> Result<Data, Error> {
+    match data {
+        Data::Array(arr) => {
+            let mut results = Vec::new();
+            for member in arr {
+                if let Some(result) = f.eval(member, env).next() {
+                    results.push(result?);
+                }
+            }
+            Ok(Data::Array(results))
+        }
+        Data::Hash(map) => {
+            let mut results = IndexMap::new();
+            for (key, value) in map {
+                if let Some(result) = f.eval(value, env).next() {
+                    results.insert(key, result?);
+                }
+            }
+            Ok(Data::Hash(results))
         }
-        let l = self.groups.len();
-        self.groups[l - 1].add_filter(t);
+        _ => Err(Error::Filter(format!(
+            "Cannot iterate over {}",
+            data.type_name()
+        ))),
     }
 }
 
-impl Filterable for FilterExpression {
-    fn get_filterables(&self) -> Vec<Box<dyn Filterable>> {
-        let mut map: Vec<Box<dyn Filterable>> = Vec::new();
-        for set in &self.groups {
-            map.push(Box::new(set.clone()));
+/// Evaluates the matching branch for every output of the condition.
+fn conditional(
+    condition: &FilterExpression,
+    then: Rc<FilterExpression>,
+    otherwise: Option<Rc<FilterExpression>>,
+    data: Data,
+    env: &Env,
+) -> Outputs {
+    let env = env.clone();
+    Box::new(
+        condition
+            .eval(data.clone(), &env)
+            .flat_map(move |result| match result {
+                Ok(value) if value.is_truthy() => then.eval(data.clone(), &env),
+                Ok(_) => match &otherwise {
+                    Some(otherwise) => otherwise.eval(data.clone(), &env),
+                    None => single(Ok(data.clone())),
+                },
+                Err(e) => single(Err(e)),
+            }),
+    )
+}
+
+/// Outputs of one `?//` alternative, switching to the next alternative on the first error.
+struct Fallback {
+    outputs: Outputs,
+    next: Option<Box<dyn FnOnce() -> Outputs>>,
+}
+
+impl Iterator for Fallback {
+    type Item = Result<Data, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.outputs.next() {
+            Some(Err(_)) if self.next.is_some() => {
+                self.outputs = (self.next.take().unwrap())();
+                self.next()
+            }
+            result => result,
+        }
+    }
+}
+
+/// Destructures `value` with the first of the `patterns` from `idx` on that succeeds and
+/// evaluates `body` with its variables bound. Variables of the other alternatives are null.
+fn destructure(
+    patterns: Rc<Vec<Pattern>>,
+    idx: usize,
+    body: Rc<FilterExpression>,
+    value: Data,
+    data: Data,
+    env: Env,
+) -> Outputs {
+    let mut scope = env.clone();
+    if patterns.len() > 1 {
+        for name in patterns.iter().flat_map(Pattern::variables) {
+            scope = scope.bind(&name, Data::Null);
+        }
+    }
+    let outputs: Outputs = match patterns[idx].bind(value.clone(), &scope) {
+        Ok(scopes) => {
+            let body = body.clone();
+            let data = data.clone();
+            Box::new(
+                scopes
+                    .into_iter()
+                    .flat_map(move |scope| body.eval(data.clone(), &scope)),
+            )
+        }
+        Err(e) => single(Err(e)),
+    };
+    if idx + 1 == patterns.len() {
+        return outputs;
+    }
+    Box::new(Fallback {
+        outputs,
+        next: Some(Box::new(move || {
+            destructure(patterns, idx + 1, body, value, data, env)
+        })),
+    })
+}
+
+fn bind(
+    source: &FilterExpression,
+    patterns: &[Pattern],
+    body: Rc<FilterExpression>,
+    data: Data,
+    env: &Env,
+) -> Outputs {
+    let patterns = Rc::new(patterns.to_vec());
+    let env = env.clone();
+    Box::new(
+        source
+            .eval(data.clone(), &env)
+            .flat_map(move |result| match result {
+                Ok(value) => destructure(
+                    patterns.clone(),
+                    0,
+                    body.clone(),
+                    value,
+                    data.clone(),
+                    env.clone(),
+                ),
+                Err(e) => single(Err(e)),
+            }),
+    )
+}
+
+/// Folds every output of `source` into the state, keeping the last output of `update`
+/// each time, or null if there is none.
+fn reduce(
+    source: Rc<FilterExpression>,
+    patterns: &[Pattern],
+    init: &FilterExpression,
+    update: Rc<FilterExpression>,
+    data: Data,
+    env: &Env,
+) -> Outputs {
+    let patterns = Rc::new(patterns.to_vec());
+    let env = env.clone();
+    Box::new(init.eval(data.clone(), &env).map(move |init| {
+        let mut state = init?;
+        for value in source.eval(data.clone(), &env) {
+            let value = value?;
+            let outputs = destructure(
+                patterns.clone(),
+                0,
+                update.clone(),
+                value,
+                state,
+                env.clone(),
+            );
+            state = Data::Null;
+            for output in outputs {
+                state = output?;
+            }
+        }
+        Ok(state)
+    }))
+}
+
+/// Streams every state the `update` goes through, or their `extract` outputs.
+fn foreach(
+    source: Rc<FilterExpression>,
+    patterns: &[Pattern],
+    init: &FilterExpression,
+    update: Rc<FilterExpression>,
+    extract: Option<Rc<FilterExpression>>,
+    data: Data,
+    env: &Env,
+) -> Outputs {
+    let patterns = Rc::new(patterns.to_vec());
+    let env = env.clone();
+    Box::new(init.eval(data.clone(), &env).flat_map(move |init| {
+        let state = match init {
+            Ok(init) => Rc::new(RefCell::new(init)),
+            Err(e) => return single(Err(e)),
+        };
+        let (patterns, update, extract, env) = (
+            patterns.clone(),
+            update.clone(),
+            extract.clone(),
+            env.clone(),
+        );
+        Box::new(source.eval(data.clone(), &env).flat_map(move |value| {
+            let value = match value {
+                Ok(value) => value,
+                Err(e) => return single(Err(e)),
+            };
+            let current = state.borrow().clone();
+            let mut outputs = Vec::new();
+            for output in destructure(
+                patterns.clone(),
+                0,
+                update.clone(),
+                value.clone(),
+                current,
+                env.clone(),
+            ) {
+                let output = match output {
+                    Ok(output) => output,
+                    Err(e) => {
+                        outputs.push(Err(e));
+                        break;
+                    }
+                };
+                *state.borrow_mut() = output.clone();
+                match &extract {
+                    Some(extract) => outputs.extend(destructure(
+                        patterns.clone(),
+                        0,
+                        extract.clone(),
+                        value.clone(),
+                        output,
+                        env.clone(),
+                    )),
+                    None => outputs.push(Ok(output)),
+                }
+            }
+            Box::new(outputs.into_iter()) as Outputs
+        })) as Outputs
+    }))
+}
+
+/// Builds objects from the entries starting at `idx`, iterating over the outputs of later
+/// entries first, so every combination of keys and values is produced.
+fn construct(
+    entries: Rc<Vec<(Rc<FilterExpression>, Rc<FilterExpression>)>>,
+    idx: usize,
+    object: IndexMap<String, Data>,
+    data: Data,
+    env: Env,
+) -> Outputs {
+    if idx == entries.len() {
+        return single(Ok(Data::Hash(object)));
+    }
+    let (key, value) = entries[idx].clone();
+    Box::new(key.eval(data.clone(), &env).flat_map(move |key| {
+        let key = match key {
+            Ok(Data::String(key)) => key,
+            Ok(key) => {
+                return single(Err(Error::Filter(format!(
+                    "Object keys must be strings, got {}",
+                    key.type_name()
+                ))))
+            }
+            Err(e) => return single(Err(e)),
+        };
+        let (entries, object, data, env) =
+            (entries.clone(), object.clone(), data.clone(), env.clone());
+        Box::new(
+            value
+                .eval(data.clone(), &env)
+                .flat_map(move |value| match value {
+                    Ok(value) => {
+                        let mut object = object.clone();
+                        object.insert(key.to_string(), value);
+                        construct(entries.clone(), idx + 1, object, data.clone(), env.clone())
+                    }
+                    Err(e) => single(Err(e)),
+                }),
+        ) as Outputs
+    }))
+}
+
+fn collect(members: &FilterExpression, data: Data, env: &Env) -> Result<Data, Error> {
+    let members = members
+        .eval(data, env)
+        .collect::<Result<Vec<Data>, Error>>()?;
+    Ok(Data::Array(members))
+}
+
+/// Indexes every output of `target` with every key, iterating over the targets first.
+fn index(target: Rc<FilterExpression>, key: &FilterExpression, data: Data, env: &Env) -> Outputs {
+    let env = env.clone();
+    Box::new(key.eval(data.clone(), &env).flat_map(move |key| {
+        match key {
+            Ok(key) => Box::new(
+                target
+                    .eval(data.clone(), &env)
+                    .map(move |target| FilterType::index(target?, &key)),
+            ) as Outputs,
+            Err(e) => single(Err(e)),
+        }
+    }))
+}
+
+/// Outputs of an optional slice bound, where a missing bound is null.
+fn bound(bound: &Option<Rc<FilterExpression>>, data: Data, env: &Env) -> Outputs {
+    match bound {
+        Some(bound) => bound.eval(data, env),
+        None => single(Ok(Data::Null)),
+    }
+}
+
+fn slice(
+    target: Rc<FilterExpression>,
+    from: &Option<Rc<FilterExpression>>,
+    to: Option<Rc<FilterExpression>>,
+    data: Data,
+    env: &Env,
+) -> Outputs {
+    let env = env.clone();
+    Box::new(bound(from, data.clone(), &env).flat_map(move |from| {
+        let from = match from {
+            Ok(from) => from,
+            Err(e) => return single(Err(e)),
+        };
+        let (target, data, env) = (target.clone(), data.clone(), env.clone());
+        Box::new(bound(&to, data.clone(), &env).flat_map(move |to| {
+            let (from, to) = match to {
+                Ok(to) => (from.clone(), to),
+                Err(e) => return single(Err(e)),
+            };
+            Box::new(
+                target
+                    .eval(data.clone(), &env)
+                    .map(move |target| FilterType::slice(target?, &from, &to)),
+            ) as Outputs
+        })) as Outputs
+    }))
+}
+
+/// Renders the template parts before `end` in front of `suffix`. Later interpolations
+/// are the outer loops, so the outputs of the first one vary fastest, like in jq.
+fn render(
+    parts: Rc<Vec<TemplatePart>>,
+    format: Option<Rc<String>>,
+    end: usize,
+    suffix: String,
+    data: Data,
+    env: Env,
+) -> Outputs {
+    if end == 0 {
+        return single(Ok(Data::String(suffix)));
+    }
+    match parts[end - 1].clone() {
+        TemplatePart::Literal(string) => {
+            render(parts, format, end - 1, string + &suffix, data, env)
+        }
+        TemplatePart::Expression(expr) => {
+            Box::new(expr.eval(data.clone(), &env).flat_map(move |value| {
+                let name = format.as_ref().map_or("text", |format| format.as_str());
+                match value.and_then(|value| crate::filter::format(name, &value)) {
+                    Ok(string) => render(
+                        parts.clone(),
+                        format.clone(),
+                        end - 1,
+                        string + &suffix,
+                        data.clone(),
+                        env.clone(),
+                    ),
+                    Err(e) => single(Err(e)),
+                }
+            }))
+        }
+    }
+}
+
+fn recurse(
+    step: Rc<FilterExpression>,
+    condition: Option<Rc<FilterExpression>>,
+    data: Data,
+    env: Env,
+) -> Outputs {
+    let current = single(Ok(data.clone()));
+    let next = lazy(move || {
+        let mut outputs = step.eval(data, &env);
+        if let Some(condition) = &condition {
+            outputs = Box::new(outputs.flat_map({
+                let (condition, env) = (condition.clone(), env.clone());
+                move |result| match result {
+                    Ok(value) => select(&condition, value, &env),
+                    Err(e) => single(Err(e)),
+                }
+            }));
         }
-        map
+        Box::new(outputs.flat_map(move |result| match result {
+            Ok(value) => recurse(step.clone(), condition.clone(), value, env.clone()),
+            Err(e) => single(Err(e)),
+        }))
+    });
+    Box::new(current.chain(next))
+}
+
+/// Outputs of a `try` body, switching over to the handler on the first error.
+pub struct Catch<T> {
+    outputs: Box<dyn Iterator<Item = Result<T, Error>>>,
+    handler: Option<CatchHandler<T>>,
+    caught: bool,
+}
+
+pub type CatchHandler<T> = Box<dyn FnOnce(Error) -> Box<dyn Iterator<Item = Result<T, Error>>>>;
+
+impl<T> Catch<T> {
+    /// Without a handler, the outputs stop silently at the first error.
+    pub fn new(
+        outputs: Box<dyn Iterator<Item = Result<T, Error>>>,
+        handler: Option<CatchHandler<T>>,
+    ) -> Self {
+        Catch {
+            outputs,
+            handler,
+            caught: false,
+        }
+    }
+}
+
+impl<T: 'static> Iterator for Catch<T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.caught {
+            // Errors of the handler itself are not caught.
+            return self.outputs.next();
+        }
+        match self.outputs.next() {
+            Some(Err(e)) => {
+                self.caught = true;
+                self.outputs = match self.handler.take() {
+                    Some(handler) => handler(e),
+                    None => Box::new(std::iter::empty()),
+                };
+                self.outputs.next()
+            }
+            result => result,
+        }
+    }
+}
+
+impl FilterExpression {
+    /// Applies the expression to `data`, resolving variables in `env`.
+    pub fn eval(&self, data: Data, env: &Env) -> Outputs {
+        match self {
+            FilterExpression::Filter(t) => t.apply(data),
+            FilterExpression::Literal(value) => single(Ok(value.clone())),
+            FilterExpression::Variable(name) => single(env.variable(name)),
+            FilterExpression::Negate(operand) => {
+                Box::new(operand.eval(data, env).map(|r| negate(r?)))
+            }
+            FilterExpression::Chain(lhs, rhs) => pipe(lhs, rhs.clone(), data, env),
+            FilterExpression::Binary(op, lhs, rhs) => match op {
+                Operator::Pipe => pipe(lhs, rhs.clone(), data, env),
+                Operator::Comma => {
+                    let rhs = rhs.clone();
+                    let env = env.clone();
+                    let first = lhs.eval(data.clone(), &env);
+                    Box::new(first.chain(lazy(move || rhs.eval(data, &env))))
+                }
+                Operator::And | Operator::Or => logical(*op, lhs, rhs.clone(), data, env),
+                Operator::Alternative => alternative(lhs, rhs.clone(), data, env),
+                _ if op.is_assignment() => assign(*op, lhs.clone(), rhs.clone(), data, env),
+                _ => combine(*op, lhs.clone(), rhs, data, env),
+            },
+            FilterExpression::Select(condition) => select(condition, data, env),
+            FilterExpression::Map(f) => single(map(f, data, env)),
+            FilterExpression::MapValues(f) => single(map_values(f, data, env)),
+            FilterExpression::If(condition, then, otherwise) => {
+                conditional(condition, then.clone(), otherwise.clone(), data, env)
+            }
+            FilterExpression::Bind(source, patterns, body) => {
+                bind(source, patterns, body.clone(), data, env)
+            }
+            FilterExpression::Define(function, rest) => {
+                rest.eval(data, &env.define(function.clone()))
+            }
+            FilterExpression::Reduce(source, patterns, init, update) => {
+                reduce(source.clone(), patterns, init, update.clone(), data, env)
+            }
+            FilterExpression::Foreach(source, patterns, init, update, extract) => foreach(
+                source.clone(),
+                patterns,
+                init,
+                update.clone(),
+                extract.clone(),
+                data,
+                env,
+            ),
+            FilterExpression::Collect(members) => single(collect(members, data, env)),
+            FilterExpression::Index(target, key) => index(target.clone(), key, data, env),
+            FilterExpression::Slice(target, from, to) => {
+                slice(target.clone(), from, to.clone(), data, env)
+            }
+            FilterExpression::Object(entries) => construct(
+                Rc::new(entries.clone()),
+                0,
+                IndexMap::new(),
+                data,
+                env.clone(),
+            ),
+            FilterExpression::Template(format, parts) => render(
+                Rc::new(parts.clone()),
+                format.clone().map(Rc::new),
+                parts.len(),
+                String::new(),
+                data,
+                env.clone(),
+            ),
+            FilterExpression::Call(name, args) => match env.function(name, args.len()) {
+                Ok(Callable::Function(function, scope)) => function.call(args, data, scope, env),
+                Ok(Callable::Closure(body, closure)) => body.eval(data, &closure),
+                Err(e) => single(Err(e)),
+            },
+            FilterExpression::Builtin(builtin, args) => builtin.eval(args, data, env),
+            FilterExpression::Recurse(step, condition) => {
+                recurse(step.clone(), condition.clone(), data, env.clone())
+            }
+            FilterExpression::Try(body, handler) => {
+                let handler = handler.clone().map(|handler| {
+                    let env = env.clone();
+                    Box::new(move |e: Error| handler.eval(e.value(), &env)) as CatchHandler<Data>
+                });
+                Box::new(Catch::new(body.eval(data, env), handler))
+            }
+        }
+    }
+}
+
+impl Filterable for FilterExpression {
+    fn apply(&self, data: Data) -> Outputs {
+        self.eval(data, &Env::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn numbers() -> Data {
+        Data::Array(vec![Data::Integer(13), Data::Integer(12)])
+    }
+
+    fn filter(t: FilterType) -> FilterExpression {
+        FilterExpression::Filter(t)
+    }
+
+    #[test]
+    fn pipe_streams_array_members() {
+        let expr = FilterExpression::binary(
+            Operator::Pipe,
+            filter(FilterType::Array),
+            filter(FilterType::Current),
+        );
+        let results: Vec<Data> = expr.apply(numbers()).map(|r| r.unwrap()).collect();
+        assert_eq!(results.len(), 2);
+        assert!(
+            matches!(results[0], Data::Integer(13)),
+            "expected first member"
+        );
+        assert!(
+            matches!(results[1], Data::Integer(12)),
+            "expected second member"
+        );
+    }
+
+    #[test]
+    fn comma_yields_each_output() {
+        let expr = FilterExpression::binary(
+            Operator::Comma,
+            FilterExpression::binary(
+                Operator::Comma,
+                filter(FilterType::Member(1)),
+                filter(FilterType::Empty),
+            ),
+            filter(FilterType::Array),
+        );
+        let results: Vec<Data> = expr.apply(numbers()).map(|r| r.unwrap()).collect();
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[0], Data::Integer(12)), "expected member");
+        assert!(
+            matches!(results[1], Data::Integer(13)),
+            "expected first member"
+        );
+        assert!(
+            matches!(results[2], Data::Integer(12)),
+            "expected second member"
+        );
+    }
+
+    #[test]
+    fn pipe_stops_at_first_error() {
+        let expr = FilterExpression::chain(filter(FilterType::Array), filter(FilterType::Array));
+        let mut results = expr.apply(numbers());
+        assert!(results.next().unwrap().is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn compares_values() {
+        assert_eq!(run(".age > 30", "{\"age\": 32}"), vec!["true"]);
+        assert_eq!(run(".age <= 30", "{\"age\": 32}"), vec!["false"]);
+        assert_eq!(run(". == 1.0", "1"), vec!["true"]);
+        assert_eq!(run(".[] != null", "[null, false]"), vec!["false", "true"]);
+        assert_eq!(
+            run("\"a\" < .[0] and .[0] < .[1]", "[[], {}]"),
+            vec!["true"]
+        );
+    }
+
+    #[test]
+    fn compares_every_combination() {
+        assert_eq!(
+            run("(1, 2) < (2, 1)", "null"),
+            vec!["true", "false", "false", "false"]
+        );
+    }
+
+    #[test]
+    fn short_circuits_logical_operators() {
+        assert_eq!(run("false and .[]", "5"), vec!["false"]);
+        assert_eq!(run("true or .[]", "5"), vec!["true"]);
+        assert_eq!(
+            run("(true, false) and (true, false)", "null"),
+            vec!["true", "false", "false"]
+        );
+        assert_eq!(
+            run("(true, false) or (true, false)", "null"),
+            vec!["true", "true", "false"]
+        );
+        assert_eq!(run(".a | not", "{\"a\": null}"), vec!["true"]);
+    }
+
+    #[test]
+    fn adds_by_type() {
+        assert_eq!(run(".a + .b", "{\"a\": 1, \"b\": 2.5}"), vec!["3.5"]);
+        assert_eq!(run(".a + null, null + .a", "{\"a\": 1}"), vec!["1", "1"]);
+        assert_eq!(run("\"ab\" + \"cd\"", "null"), vec!["\"abcd\""]);
+        assert_eq!(run(".[0] + .[1]", "[[1], [2, 3]]"), vec!["[1,2,3]"]);
+        assert_eq!(
+            run(
+                ".[0] + .[1]",
+                "[{\"a\": {\"b\": 1}, \"c\": 1}, {\"a\": {\"d\": 2}}]"
+            ),
+            vec!["{\"a\":{\"d\":2},\"c\":1}"]
+        );
+    }
+
+    #[test]
+    fn multiplies_by_type() {
+        assert_eq!(
+            run(
+                ".[0] * .[1]",
+                "[{\"a\": {\"b\": 1}, \"c\": 1}, {\"a\": {\"d\": 2}}]"
+            ),
+            vec!["{\"a\":{\"b\":1,\"d\":2},\"c\":1}"]
+        );
+        assert_eq!(
+            run("\"ab\" * 3, 0 * \"ab\"", "null"),
+            vec!["\"ababab\"", "null"]
+        );
+        assert_eq!(
+            run("\"ab\" * 0.5, \"ab\" * 1.5, \"ab\" * 2.9", "null"),
+            vec!["\"ab\"", "\"ab\"", "\"abab\""]
+        );
+        assert_eq!(run("2 * 3 + 4 * 5", "null"), vec!["26"]);
+    }
+
+    #[test]
+    fn subtracts_divides_and_modulos() {
+        assert_eq!(
+            run(".[0] - .[1]", "[[1, 2, 3, 2, 4], [2, 4]]"),
+            vec!["[1,3]"]
+        );
+        assert_eq!(run("10 - 2 - 3", "null"), vec!["5"]);
+        assert_eq!(
+            run("8 / 2, 1 / 4, 7 % 3, -7 % 3", "null"),
+            vec!["4", "0.25", "1", "-1"]
+        );
+        assert_eq!(
+            run(". / \", \"", "\"a, b, c\""),
+            vec!["[\"a\",\"b\",\"c\"]"]
+        );
+        assert_eq!(run(". / \"\"", "\"abc\""), vec!["[\"a\",\"b\",\"c\"]"]);
+    }
+
+    #[test]
+    fn promotes_overflowing_integers() {
+        assert_eq!(
+            run(". + 1", "9223372036854775807"),
+            vec!["9.223372036854776e+18"]
+        );
+        assert_eq!(
+            run(". * -2", "9223372036854775807"),
+            vec!["-1.8446744073709552e+19"]
+        );
+    }
+
+    #[test]
+    fn reports_arithmetic_errors() {
+        let filters = crate::parser::ExpressionParser::new(". / 0")
+            .parse()
+            .unwrap();
+        assert!(
+            filters.apply(Data::Integer(1)).next().unwrap().is_err(),
+            "should not be a success"
+        );
+        let filters = crate::parser::ExpressionParser::new(". + 1")
+            .parse()
+            .unwrap();
+        assert!(
+            filters.apply(Data::Boolean(true)).next().unwrap().is_err(),
+            "should not be a success"
+        );
+        let filters = crate::parser::ExpressionParser::new(". * 1e30")
+            .parse()
+            .unwrap();
+        assert!(
+            filters
+                .apply(Data::String("ab".to_string()))
+                .next()
+                .unwrap()
+                .is_err(),
+            "should not be a success"
+        );
+    }
+
+    #[test]
+    fn selects_matching_inputs() {
+        let people =
+            "[{\"name\": \"a\", \"isActive\": true}, {\"name\": \"b\", \"isActive\": false}]";
+        assert_eq!(
+            run(".[] | select(.isActive) | .name", people),
+            vec!["\"a\""]
+        );
+        assert_eq!(
+            run("select(.[] > 1)", "[1, 2, 3]"),
+            vec!["[1,2,3]", "[1,2,3]"]
+        );
+    }
+
+    #[test]
+    fn maps_members() {
+        assert_eq!(run("map(. * 2)", "[1, 2]"), vec!["[2,4]"]);
+        assert_eq!(run("map(., .)", "{\"a\": 1}"), vec!["[1,1]"]);
+        assert_eq!(run("map_values(empty)", "{\"a\": 1}"), vec!["{}"]);
+        assert_eq!(
+            run("map_values(. + 1, 10)", "{\"a\": 1, \"b\": 2}"),
+            vec!["{\"a\":2,\"b\":3}"]
+        );
+        assert_eq!(run("map_values(select(. > 1))", "[1, 2, 3]"), vec!["[2,3]"]);
+    }
+
+    #[test]
+    fn evaluates_conditionals() {
+        let filter =
+            ".[] | if . < 0 then \"negative\" elif . == 0 then \"zero\" else \"positive\" end";
+        assert_eq!(
+            run(filter, "[-1, 0, 1]"),
+            vec!["\"negative\"", "\"zero\"", "\"positive\""]
+        );
+        assert_eq!(
+            run("if .[] then 1 end", "[true, false]"),
+            vec!["1", "[true,false]"]
+        );
+    }
+
+    #[test]
+    fn binds_variables() {
+        let input = "{\"name\": \"x\", \"friends\": [{\"name\": \"y\"}, {\"name\": \"z\"}]}";
+        assert_eq!(
+            run(
+                ". as $root | .friends[] | .name + \" of \" + $root.name",
+                input
+            ),
+            vec!["\"y of x\"", "\"z of x\""]
+        );
+        assert_eq!(run(".[] as $x | $x * 2", "[1, 2]"), vec!["2", "4"]);
+        assert_eq!(run(". as $x | (. as $x | $x + 1), $x", "1"), vec!["2", "1"]);
+    }
+
+    #[test]
+    fn destructures_values() {
+        assert_eq!(
+            run(
+                ". as [$a, {b: $c, $d}] | $a, $c, $d",
+                "[1, {\"b\": 2, \"d\": 3}]"
+            ),
+            vec!["1", "2", "3"]
+        );
+        assert_eq!(
+            run(". as {$a: [$b]} | $a, $b", "{\"a\": [1]}"),
+            vec!["[1]", "1"]
+        );
+        assert_eq!(
+            run(". as {(.k, \"j\"): $v} | $v", "{\"k\": \"j\", \"j\": 5}"),
+            vec!["5", "5"]
+        );
+        assert_eq!(run(". as [$a] | $a", "null"), vec!["null"]);
+    }
+
+    #[test]
+    fn tries_destructuring_alternatives() {
+        let filter = ".[] as [$a] ?// {$a} ?// $a | $a";
+        assert_eq!(run(filter, "[[1], {\"a\": 2}, 3]"), vec!["1", "2", "3"]);
+        assert_eq!(
+            run(".[] as [$a] ?// $b | $a, $b", "[[1], 2]"),
+            vec!["1", "null", "null", "2"]
+        );
+        let filters = crate::parser::ExpressionParser::new(". as [$a] ?// {$a} | $a")
+            .parse()
+            .unwrap();
+        assert!(
+            filters.apply(Data::Integer(1)).next().unwrap().is_err(),
+            "last alternative errors should pass through"
+        );
+    }
+
+    #[test]
+    fn calls_defined_functions() {
+        assert_eq!(
+            run(
+                "def name: .name; .[] | name",
+                "[{\"name\": \"a\"}, {\"name\": \"b\"}]"
+            ),
+            vec!["\"a\"", "\"b\""]
+        );
+        assert_eq!(run("def twice(f): f | f; twice(. * 3)", "2"), vec!["18"]);
+        assert_eq!(
+            run("def pair($a; $b): $a * 10 + $b; pair(1, 2; 3, 4)", "null"),
+            vec!["13", "14", "23", "24"]
+        );
+        assert_eq!(run("def f(x): x * 2; f(.)", "5"), vec!["10"]);
+    }
+
+    #[test]
+    fn recurses_into_functions() {
+        let filter = "def fac: if . <= 1 then 1 else . * (. - 1 | fac) end; fac";
+        assert_eq!(run(filter, "10"), vec!["3628800"]);
+        let filter = "def down: if . > 0 then ., (. - 1 | down) else empty end; down";
+        assert_eq!(run(filter, "3"), vec!["3", "2", "1"]);
+    }
+
+    #[test]
+    fn scopes_functions_lexically() {
+        let filter = "def f: 1; def g: f; def f: 2; g, f";
+        assert_eq!(run(filter, "null"), vec!["1", "2"]);
+        let filter = "1 as $x | def f: $x; 2 as $x | f, $x";
+        assert_eq!(run(filter, "null"), vec!["1", "2"]);
+        let filter = "def f(g): def h: g; 3 as $g | h; 5 as $g | f($g)";
+        assert_eq!(run(filter, "null"), vec!["5"]);
+    }
+
+    #[test]
+    fn reduces_outputs() {
+        let people = "[{\"age\": 20}, {\"age\": 30}]";
+        assert_eq!(run("reduce .[] as $x (0; . + $x.age)", people), vec!["50"]);
+        assert_eq!(
+            run(
+                "reduce .[] as [$a, $b] (0; . + $a * $b)",
+                "[[1, 2], [3, 4]]"
+            ),
+            vec!["14"]
+        );
+        assert_eq!(
+            run("reduce .[] as $x (0, 10; . + $x)", "[1, 2]"),
+            vec!["3", "13"]
+        );
+        assert_eq!(run("reduce .[] as $x (0; empty)", "[1]"), vec!["null"]);
+        assert_eq!(run("reduce empty as $x (5; . + 1)", "null"), vec!["5"]);
+    }
+
+    #[test]
+    fn streams_foreach_states() {
+        assert_eq!(
+            run("foreach .[] as $x (0; . + $x)", "[1, 2, 3]"),
+            vec!["1", "3", "6"]
+        );
+        assert_eq!(
+            run("foreach .[] as $x (0; . + $x; . * 10 + $x)", "[1, 2]"),
+            vec!["11", "32"]
+        );
+        assert_eq!(
+            run("foreach .[] as $x (0; . + $x, . - $x)", "[1, 2]"),
+            vec!["1", "-1", "1", "-3"]
+        );
+        let filters = crate::parser::ExpressionParser::new("foreach .[] as $x (0; . + $x)")
+            .parse()
+            .unwrap();
+        let mut results = filters.apply(load_str("[1, \"a\"]").unwrap());
+        assert!(
+            results.next().unwrap().is_ok(),
+            "should stream the first state"
+        );
+        assert!(results.next().unwrap().is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn constructs_objects() {
+        let input = "{\"name\": \"x\", \"email\": \"e\", \"k\": \"key\", \"v\": 1}";
+        assert_eq!(
+            run("{name, email}", input),
+            vec!["{\"name\":\"x\",\"email\":\"e\"}"]
+        );
+        assert_eq!(
+            run("{(.k): .v, \"literal\": 1}", input),
+            vec!["{\"key\":1,\"literal\":1}"]
+        );
+        assert_eq!(
+            run("{\"\\(.k)\": .v, \"\\(\"na\")me\"}", input),
+            vec!["{\"key\":1,\"name\":\"x\"}"]
+        );
+        assert_eq!(
+            run(".name as $n | {$n, @base64: .name}", input),
+            vec![
+                "{\"n\":\"x\",\"eyJuYW1lIjoieCIsImVtYWlsIjoiZSIsImsiOiJrZXkiLCJ2IjoxfQ==\":\"x\"}"
+            ]
+        );
+        assert_eq!(
+            run(
+                ". as $root | .friends[] | {name, owner: $root.name}",
+                "{\"name\": \"a\", \"friends\": [{\"name\": \"b\"}]}"
+            ),
+            vec!["{\"name\":\"b\",\"owner\":\"a\"}"]
+        );
+    }
+
+    #[test]
+    fn constructs_every_combination() {
+        assert_eq!(
+            run("{a: (1, 2), (\"b\", \"c\"): (3, 4)}", "null"),
+            vec![
+                "{\"a\":1,\"b\":3}",
+                "{\"a\":1,\"b\":4}",
+                "{\"a\":1,\"c\":3}",
+                "{\"a\":1,\"c\":4}",
+                "{\"a\":2,\"b\":3}",
+                "{\"a\":2,\"b\":4}",
+                "{\"a\":2,\"c\":3}",
+                "{\"a\":2,\"c\":4}",
+            ]
+        );
+        assert_eq!(run("{a: empty}", "null"), Vec::<String>::new());
+        let filters = crate::parser::ExpressionParser::new("{(1): 2}")
+            .parse()
+            .unwrap();
+        assert!(
+            filters.apply(Data::Null).next().unwrap().is_err(),
+            "object keys must be strings"
+        );
+    }
+
+    #[test]
+    fn collects_arrays() {
+        let people = "[{\"name\": \"a\"}, {\"name\": \"b\"}]";
+        assert_eq!(run("[.[] | .name]", people), vec!["[\"a\",\"b\"]"]);
+        assert_eq!(
+            run("[], [empty], [1, 2]", "null"),
+            vec!["[]", "[]", "[1,2]"]
+        );
+        assert_eq!(
+            run("reduce .[] as $x ([]; [$x] + .)", "[1, 2, 3]"),
+            vec!["[3,2,1]"]
+        );
+    }
+
+    #[test]
+    fn indexes_with_expressions() {
+        let input = "{\"idx\": \"b\", \"b\": 2, \"arr\": [10, 20, 30], \"i\": 1}";
+        assert_eq!(run(".[.idx]", input), vec!["2"]);
+        assert_eq!(run(".arr[.i]", input), vec!["20"]);
+        assert_eq!(run("\"b\" as $k | .[$k]", input), vec!["2"]);
+        assert_eq!(
+            run(".arr[-1], .arr[-4], .arr[1.7]", input),
+            vec!["30", "null", "20"]
+        );
+        assert_eq!(run(".arr[0, 2]", input), vec!["10", "30"]);
+        assert_eq!(run(".[.idx, \"i\"]", input), vec!["2", "1"]);
+    }
+
+    #[test]
+    fn slices_with_expressions() {
+        let input = "{\"arr\": [1, 2, 3, 4], \"n\": 2, \"s\": \"héllo\"}";
+        assert_eq!(run(".arr[.n:]", input), vec!["[3,4]"]);
+        assert_eq!(run(".arr[:-1], .arr[-2:]", input), vec!["[1,2,3]", "[3,4]"]);
+        assert_eq!(run(".arr[.n:1], .arr[:0]", input), vec!["[]", "[]"]);
+        assert_eq!(
+            run(".s[1:.n + 1], .s[2:]", input),
+            vec!["\"él\"", "\"llo\""]
+        );
+        let filters = crate::parser::ExpressionParser::new(".[\"a\":]")
+            .parse()
+            .unwrap();
+        assert!(
+            filters
+                .apply(load_str("[]").unwrap())
+                .next()
+                .unwrap()
+                .is_err(),
+            "should not be a success"
+        );
+    }
+
+    #[test]
+    fn interpolates_strings() {
+        let input = r#"{"name": "Zoë", "age": 32, "tags": ["a", "b"]}"#;
+        assert_eq!(
+            run(r#""Hello \(.name), you are \(.age)""#, input),
+            vec![r#""Hello Zoë, you are 32""#]
+        );
+        assert_eq!(
+            run(r#""\(.tags) \(.tags[])!""#, input),
+            vec![r#""[\"a\",\"b\"] a!""#, r#""[\"a\",\"b\"] b!""#]
+        );
+        assert_eq!(
+            run(r#""\(1, 2)-\(3, 4)""#, "null"),
+            vec![r#""1-3""#, r#""2-3""#, r#""1-4""#, r#""2-4""#]
+        );
+        assert_eq!(
+            run(r#""outer \("inner \(.age)")""#, input),
+            vec![r#""outer inner 32""#]
+        );
+        assert_eq!(
+            run(r#"{"a \"quoted\" key": 1} | keys[0]"#, "null"),
+            vec![r#""a \"quoted\" key""#]
+        );
+    }
+
+    #[test]
+    fn formats_interpolations() {
+        assert_eq!(
+            run(r#"@base64 "name: \(.name)""#, r#"{"name": "x"}"#),
+            vec![r#""name: eA==""#]
+        );
+        assert_eq!(
+            run(r#"@json "v=\(.)", @text "v=\(.)""#, r#""s""#),
+            vec![r#""v=\"s\"""#, r#""v=s""#]
+        );
+        assert_eq!(run("@base64", r#""hello""#), vec![r#""aGVsbG8=""#]);
+    }
+
+    #[test]
+    fn catches_errors() {
+        let input = r#"[{"a": 1}, 2, {"a": 3}]"#;
+        assert_eq!(run(".[] | .a?", input), vec!["1", "3"]);
+        assert_eq!(run("[.[] | try .a]", input), vec!["[1,3]"]);
+        assert_eq!(run(r#"try (1, error("x"), 2)"#, "null"), vec!["1"]);
+        assert_eq!(
+            run(
+                r#"try error("x") catch ., try error({a: 1}) catch .a"#,
+                "null"
+            ),
+            vec![r#""x""#, "1"]
+        );
+        assert_eq!(
+            run("try .a catch .", "1"),
+            vec![r#""Cannot index number with \"a\"""#]
+        );
+        assert_eq!(run(".[]?", "1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn raises_errors() {
+        let filters = crate::parser::ExpressionParser::new(r#"try error("x") catch error"#)
+            .parse()
+            .unwrap();
+        let outputs: Vec<Result<Data, Error>> = filters.apply(Data::Null).collect();
+        assert!(
+            matches!(&outputs[..], [Err(Error::Raised(Data::String(msg)))] if msg == "x"),
+            "expected the handler to raise the caught value"
+        );
+        assert_eq!(
+            format!("{}", Error::Raised(Data::Integer(1))),
+            "[Filter] ERROR: 1 (not a string)"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_alternatives() {
+        let input = r#"{"name": "x", "off": false, "list": [null, 1, false, 2]}"#;
+        assert_eq!(
+            run(r#".nickname // .name // "anon""#, input),
+            vec![r#""x""#]
+        );
+        assert_eq!(run(r#".off // "on""#, input), vec![r#""on""#]);
+        assert_eq!(run(".list[] // 3", input), vec!["1", "2"]);
+        assert_eq!(run("(.list[] | select(. == null)) // 3", input), vec!["3"]);
+        assert_eq!(run(r#"(1, error("x"), 2) // 3"#, "null"), vec!["1", "2"]);
+        assert_eq!(run(r#"error("x") // 3"#, "null"), vec!["3"]);
+        assert_eq!(run("empty // 1, 2", "null"), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn recurses_in_document_order() {
+        let input = r#"{"a": {"email": "x", "b": [{"email": "y"}, 1]}, "email": "z"}"#;
+        assert_eq!(
+            run(".. | .email? // empty", input),
+            vec![r#""z""#, r#""x""#, r#""y""#]
+        );
+        assert_eq!(run("[..]", "[[1], 2]"), vec!["[[[1],2],[1],1,2]"]);
+        assert_eq!(
+            run("[recurse] == [..], [recurse(.[]?)] == [..]", "[[1], 2]"),
+            vec!["true", "true"]
+        );
+        assert_eq!(
+            run("recurse(if . < 3 then . + 1 else empty end)", "0"),
+            vec!["0", "1", "2", "3"]
+        );
+        assert_eq!(run("recurse(. * .; . < 100)", "2"), vec!["2", "4", "16"]);
+    }
+
+    #[test]
+    fn assigns_values_at_paths() {
+        let input = r#"[{"isActive": true, "tags": ["a"]}, {"isActive": false, "tags": []}]"#;
+        assert_eq!(
+            run(".[] .isActive |= not", input),
+            vec![r#"[{"isActive":false,"tags":["a"]},{"isActive":true,"tags":[]}]"#]
+        );
+        assert_eq!(
+            run(r#".[0].tags += ["new"] | .[0].tags"#, input),
+            vec![r#"["a","new"]"#]
+        );
+        assert_eq!(run(".a = (1, 2)", "{}"), vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+        assert_eq!(run(".a = .b", r#"{"b": 3}"#), vec![r#"{"b":3,"a":3}"#]);
+        assert_eq!(run(".[] |= empty", "[1, 2, 3]"), vec!["[]"]);
+        assert_eq!(run(".[] |= (., 10)", "[1, 2]"), vec!["[1,2]"]);
+        assert_eq!(
+            run(
+                ".a -= 1 | .b *= 2 | .c /= 2 | .d %= 2",
+                r#"{"a": 1, "b": 2, "c": 3, "d": 5}"#
+            ),
+            vec![r#"{"a":0,"b":4,"c":1.5,"d":1}"#]
+        );
+        assert_eq!(run(".[] //= 0", "[null, 1, false]"), vec!["[0,1,0]"]);
+        assert_eq!(run(".a.b[1] = 1", "null"), vec![r#"{"a":{"b":[null,1]}}"#]);
+    }
+
+    #[test]
+    fn displays_parentheses_where_needed() {
+        let grouped = FilterExpression::binary(
+            Operator::Comma,
+            filter(FilterType::Member(0)),
+            FilterExpression::binary(
+                Operator::Pipe,
+                filter(FilterType::Member(1)),
+                filter(FilterType::Keys),
+            ),
+        );
+        assert_eq!(format!("{}", grouped), ".[0],(.[1]|keys)");
+
+        let nested = FilterExpression::binary(
+            Operator::Pipe,
+            FilterExpression::binary(
+                Operator::Pipe,
+                filter(FilterType::Member(0)),
+                filter(FilterType::Member(1)),
+            ),
+            filter(FilterType::Keys),
+        );
+        assert_eq!(format!("{}", nested), "(.[0]|.[1])|keys");
     }
 }
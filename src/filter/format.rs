@@ -0,0 +1,190 @@
+use super::*;
+use crate::output::*;
+
+/// Names of the `@format` string formatters.
+pub const FORMATS: [&str; 9] = [
+    "text", "json", "html", "uri", "csv", "tsv", "sh", "base64", "base64d",
+];
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Strings as they are, and any other value as JSON.
+fn text(data: &Data) -> String {
+    match data {
+        Data::String(s) => s.to_string(),
+        _ => to_json(data, JsonStyle::Compact),
+    }
+}
+
+/// Members of a `@csv` or `@tsv` row, with `quote` applied to strings.
+fn row(name: &str, data: &Data, quote: fn(&str) -> String) -> Result<Vec<String>, Error> {
+    let members = match data {
+        Data::Array(members) => members,
+        _ => {
+            return Err(Error::Filter(format!(
+                "{} cannot be {}-formatted, only an array can be",
+                data.type_name(),
+                name
+            )))
+        }
+    };
+    members
+        .iter()
+        .map(|member| match member {
+            Data::Null => Ok(String::new()),
+            Data::String(s) => Ok(quote(s)),
+            Data::Integer(_) | Data::Real(_) | Data::Boolean(_) => Ok(text(member)),
+            _ => Err(Error::Filter(format!(
+                "{} is not valid in a {} row",
+                member.type_name(),
+                name
+            ))),
+        })
+        .collect()
+}
+
+fn shell_quote(data: &Data) -> Result<String, Error> {
+    match data {
+        Data::String(s) => Ok(format!("'{}'", s.replace('\'', "'\\''"))),
+        Data::Array(_) | Data::Hash(_) => Err(Error::Filter(format!(
+            "{} can not be escaped for shell",
+            data.type_name()
+        ))),
+        _ => Ok(text(data)),
+    }
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn base64_decode(encoded: &str) -> Result<String, Error> {
+    let mut bytes = Vec::new();
+    let (mut n, mut bits) = (0u32, 0);
+    for c in encoded.trim_end_matches('=').bytes() {
+        let value = match BASE64.iter().position(|&b| b == c) {
+            Some(value) => value as u32,
+            None => {
+                return Err(Error::Filter(format!(
+                    "{} is not valid base64 data",
+                    encoded
+                )))
+            }
+        };
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((n >> bits & 255) as u8);
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// Applies the `@name` formatter to `data`.
+pub fn format(name: &str, data: &Data) -> Result<String, Error> {
+    let formatted = match name {
+        "text" => text(data),
+        "json" => to_json(data, JsonStyle::Compact),
+        "html" => text(data)
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('\'', "&#39;")
+            .replace('"', "&quot;"),
+        "uri" => text(data)
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect(),
+        "csv" => row(name, data, |s| format!("\"{}\"", s.replace('"', "\"\"")))?.join(","),
+        "tsv" => row(name, data, |s| {
+            s.replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+        })?
+        .join("\t"),
+        "sh" => match data {
+            Data::Array(members) => members
+                .iter()
+                .map(shell_quote)
+                .collect::<Result<Vec<String>, Error>>()?
+                .join(" "),
+            _ => shell_quote(data)?,
+        },
+        "base64" => base64_encode(text(data).as_bytes()),
+        "base64d" => base64_decode(&text(data))?,
+        _ => return Err(Error::Filter(format!("{} is not a valid format", name))),
+    };
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn string(s: &str) -> Data {
+        Data::String(s.to_string())
+    }
+
+    fn formatted(name: &str, data: Data) -> String {
+        format(name, &data).unwrap()
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(formatted("base64", string("hello")), "aGVsbG8=");
+        assert_eq!(formatted("base64", string("hi")), "aGk=");
+        assert_eq!(formatted("base64", Data::Integer(123)), "MTIz");
+        assert_eq!(formatted("base64d", string("aGVsbG8=")), "hello");
+        assert_eq!(formatted("base64d", string("aGk")), "hi");
+        assert!(format("base64d", &string("a*")).is_err(), "not base64");
+    }
+
+    #[test]
+    fn formats_rows() {
+        let row = Data::Array(vec![
+            Data::Integer(1),
+            string("a \"b\"\tc"),
+            Data::Null,
+            Data::Boolean(true),
+        ]);
+        assert_eq!(formatted("csv", row.clone()), "1,\"a \"\"b\"\"\tc\",,true");
+        assert_eq!(formatted("tsv", row), "1\ta \"b\"\\tc\t\ttrue");
+        assert!(format("csv", &string("a")).is_err(), "not a row");
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(
+            formatted("html", string("<a href='x'>&</a>")),
+            "&lt;a href=&#39;x&#39;&gt;&amp;&lt;/a&gt;"
+        );
+        assert_eq!(formatted("uri", string("a b/ü~")), "a%20b%2F%C3%BC~");
+        assert_eq!(
+            formatted("sh", Data::Array(vec![string("it's"), Data::Integer(1)])),
+            "'it'\\''s' 1"
+        );
+        assert_eq!(formatted("json", string("x")), "\"x\"");
+        assert_eq!(formatted("text", Data::Array(vec![])), "[]");
+    }
+}
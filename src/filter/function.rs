@@ -0,0 +1,57 @@
+use super::*;
+use std::rc::Rc;
+
+/// User-defined function, as in `def name(f; $x): body;`.
+///
+/// Parameters are filters; `$x` parameters are bound as variables by the body itself.
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Rc<FilterExpression>,
+}
+
+impl Function {
+    /// Evaluates the body in `scope`, the scope the function was defined in, with every
+    /// parameter bound to the matching argument as a closure over the caller's `env`.
+    pub fn call(
+        &self,
+        args: &[Rc<FilterExpression>],
+        data: Data,
+        scope: Env,
+        env: &Env,
+    ) -> Outputs {
+        self.body.eval(data, &self.bind_args(args, scope, env))
+    }
+
+    /// Like `call`, tracking the path of every output within `data`, found at `path`.
+    pub fn call_paths(
+        &self,
+        args: &[Rc<FilterExpression>],
+        path: Vec<Data>,
+        data: Data,
+        scope: Env,
+        env: &Env,
+    ) -> Paths {
+        self.body
+            .paths(path, data, &self.bind_args(args, scope, env))
+    }
+
+    fn bind_args(&self, args: &[Rc<FilterExpression>], scope: Env, env: &Env) -> Env {
+        let mut scope = scope;
+        for (param, arg) in self.params.iter().zip(args) {
+            scope = scope.bind_closure(param, arg.clone(), env.clone());
+        }
+        scope
+    }
+}
+
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "def {}", self.name)?;
+        if !self.params.is_empty() {
+            write!(f, "({})", self.params.join(";"))?;
+        }
+        write!(f, ": {};", self.body)
+    }
+}
@@ -4,25 +4,67 @@ use crate::error::*;
 mod r#type;
 pub use r#type::*;
 
-mod set;
-pub use set::*;
+mod operator;
+pub use operator::*;
 
-mod group;
-pub use group::*;
+mod arithmetic;
+pub use arithmetic::*;
 
 mod expression;
 pub use expression::*;
 
+mod env;
+pub use env::*;
+
+mod pattern;
+pub use pattern::*;
+
+mod function;
+pub use function::*;
+
+mod format;
+pub use format::*;
+
+mod builtin;
+pub use builtin::*;
+
+mod path;
+pub use path::*;
+
+mod regexp;
+pub use regexp::*;
+
+/// Lazily evaluated results of applying a filter: zero or more values,
+/// stopping at the first error.
+pub type Outputs = Box<dyn Iterator<Item = Result<Data, Error>>>;
+
+pub fn single(result: Result<Data, Error>) -> Outputs {
+    Box::new(std::iter::once(result))
+}
+
+pub fn none() -> Outputs {
+    Box::new(std::iter::empty())
+}
+
+/// Defers producing the outputs until they are first asked for,
+/// which keeps recursive filters from being expanded eagerly.
+pub fn lazy<F: FnOnce() -> Outputs + 'static>(f: F) -> Outputs {
+    Box::new(std::iter::once_with(f).flatten())
+}
+
 pub trait Filterable {
-    fn get_filterables(&self) -> Vec<Box<dyn Filterable>>;
-
-    fn apply(&self, original_data: Data) -> Result<Data, Error> {
-        let mut data = original_data;
-        let filterables = self.get_filterables();
-        for filterable in filterables {
-            let new_data = filterable.apply(data)?;
-            data = new_data.clone();
-        }
-        Ok(data)
-    }
+    fn apply(&self, data: Data) -> Outputs;
+}
+
+/// Applies the filter to JSON (or YAML) input, printing every result as compact JSON.
+#[cfg(test)]
+fn run(filter: &str, input: &str) -> Vec<String> {
+    use crate::output::*;
+    let filters = crate::parser::ExpressionParser::new(filter)
+        .parse()
+        .unwrap();
+    filters
+        .apply(load_str(input).unwrap())
+        .map(|r| to_json(&r.unwrap(), JsonStyle::Compact))
+        .collect()
 }
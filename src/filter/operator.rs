@@ -0,0 +1,146 @@
+use super::*;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Pipe,
+    Comma,
+    /// `a // b`: the truthy outputs of `a`, or else the outputs of `b`.
+    Alternative,
+    /// `path = value`: sets every path of the left-hand side to the value.
+    Assign,
+    /// `path |= f`: replaces every value at the paths of the left-hand side with
+    /// the first output of `f`, deleting it if there is none.
+    Update,
+    /// `path += value` and the like: updates with the arithmetic or `//` operator.
+    AddAssign,
+    SubtractAssign,
+    MultiplyAssign,
+    DivideAssign,
+    ModuloAssign,
+    AlternativeAssign,
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+impl Operator {
+    /// Binding strength, loosest first, following jq's grammar.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Operator::Pipe => 1,
+            Operator::Comma => 2,
+            Operator::Alternative => 3,
+            Operator::Assign
+            | Operator::Update
+            | Operator::AddAssign
+            | Operator::SubtractAssign
+            | Operator::MultiplyAssign
+            | Operator::DivideAssign
+            | Operator::ModuloAssign
+            | Operator::AlternativeAssign => 4,
+            Operator::Or => 5,
+            Operator::And => 6,
+            Operator::Equal
+            | Operator::NotEqual
+            | Operator::Less
+            | Operator::LessEqual
+            | Operator::Greater
+            | Operator::GreaterEqual => 7,
+            Operator::Add | Operator::Subtract => 8,
+            Operator::Multiply | Operator::Divide | Operator::Modulo => 9,
+        }
+    }
+
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, Operator::Pipe | Operator::Alternative)
+    }
+
+    /// Non-associative operators can't be chained without parentheses, as in `1 < 2 < 3`.
+    pub fn is_non_associative(&self) -> bool {
+        self.precedence() == Operator::Equal.precedence() || self.is_assignment()
+    }
+
+    pub fn is_assignment(&self) -> bool {
+        self.precedence() == Operator::Assign.precedence()
+    }
+
+    /// Operator an arithmetic assignment such as `+=` combines the old and new values with.
+    pub fn assigned(&self) -> Option<Operator> {
+        match self {
+            Operator::AddAssign => Some(Operator::Add),
+            Operator::SubtractAssign => Some(Operator::Subtract),
+            Operator::MultiplyAssign => Some(Operator::Multiply),
+            Operator::DivideAssign => Some(Operator::Divide),
+            Operator::ModuloAssign => Some(Operator::Modulo),
+            Operator::AlternativeAssign => Some(Operator::Alternative),
+            _ => None,
+        }
+    }
+
+    /// Computes the result of the operator for a single pair of operand values.
+    pub fn compute(&self, lhs: Data, rhs: &Data) -> Result<Data, Error> {
+        let ord = || lhs.compare(rhs);
+        let result = match self {
+            Operator::Equal => ord() == Ordering::Equal,
+            Operator::NotEqual => ord() != Ordering::Equal,
+            Operator::Less => ord() == Ordering::Less,
+            Operator::LessEqual => ord() != Ordering::Greater,
+            Operator::Greater => ord() == Ordering::Greater,
+            Operator::GreaterEqual => ord() != Ordering::Less,
+            Operator::Add => return add(lhs, rhs),
+            Operator::Subtract => return subtract(lhs, rhs),
+            Operator::Multiply => return multiply(lhs, rhs),
+            Operator::Divide => return divide(lhs, rhs),
+            Operator::Modulo => return modulo(lhs, rhs),
+            _ => {
+                return Err(Error::Filter(format!(
+                    "{} can not be computed from values",
+                    self
+                )))
+            }
+        };
+        Ok(Data::Boolean(result))
+    }
+}
+
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Operator::Pipe => write!(f, "|"),
+            Operator::Comma => write!(f, ","),
+            Operator::Alternative => write!(f, "//"),
+            Operator::Assign => write!(f, "="),
+            Operator::Update => write!(f, "|="),
+            Operator::AddAssign => write!(f, "+="),
+            Operator::SubtractAssign => write!(f, "-="),
+            Operator::MultiplyAssign => write!(f, "*="),
+            Operator::DivideAssign => write!(f, "/="),
+            Operator::ModuloAssign => write!(f, "%="),
+            Operator::AlternativeAssign => write!(f, "//="),
+            Operator::Or => write!(f, "or"),
+            Operator::And => write!(f, "and"),
+            Operator::Equal => write!(f, "=="),
+            Operator::NotEqual => write!(f, "!="),
+            Operator::Less => write!(f, "<"),
+            Operator::LessEqual => write!(f, "<="),
+            Operator::Greater => write!(f, ">"),
+            Operator::GreaterEqual => write!(f, ">="),
+            Operator::Add => write!(f, "+"),
+            Operator::Subtract => write!(f, "-"),
+            Operator::Multiply => write!(f, "*"),
+            Operator::Divide => write!(f, "/"),
+            Operator::Modulo => write!(f, "%"),
+        }
+    }
+}
@@ -0,0 +1,656 @@
+use super::*;
+use crate::output::*;
+use indexmap::IndexMap;
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Outputs of a filter along with their paths within the input,
+/// as the keys, indices and `{"start", "end"}` slices leading to them.
+pub type Paths = Box<dyn Iterator<Item = Result<(Vec<Data>, Data), Error>>>;
+
+fn located(path: Vec<Data>, data: Data) -> Paths {
+    Box::new(std::iter::once(Ok((path, data))))
+}
+
+fn failed(e: Error) -> Paths {
+    Box::new(std::iter::once(Err(e)))
+}
+
+/// Errors for every output of a filter that doesn't refer to a part of its input.
+fn invalid(outputs: Outputs) -> Paths {
+    Box::new(outputs.map(|result| {
+        Err(Error::Filter(format!(
+            "Invalid path expression with result {}",
+            to_json(&result?, JsonStyle::Compact)
+        )))
+    }))
+}
+
+/// Follows the path from `path` further down by `key`.
+fn descend(mut path: Vec<Data>, data: Data, key: Data) -> Result<(Vec<Data>, Data), Error> {
+    let value = member(data, &key)?;
+    path.push(key);
+    Ok((path, value))
+}
+
+/// The member of `data` at a single path component.
+fn member(data: Data, key: &Data) -> Result<Data, Error> {
+    match key {
+        Data::Hash(slice) => FilterType::slice(data, bound(slice, "start"), bound(slice, "end")),
+        _ => FilterType::index(data, key),
+    }
+}
+
+fn bound<'a>(slice: &'a IndexMap<String, Data>, name: &str) -> &'a Data {
+    slice.get(name).unwrap_or(&Data::Null)
+}
+
+fn slice_key(from: Data, to: Data) -> Data {
+    let mut key = IndexMap::new();
+    key.insert("start".to_string(), from);
+    key.insert("end".to_string(), to);
+    Data::Hash(key)
+}
+
+impl FilterType {
+    /// Like `apply`, tracking the path of every output within the data at `path`.
+    pub fn paths(&self, path: Vec<Data>, data: Data) -> Paths {
+        match self {
+            FilterType::Current => located(path, data),
+            FilterType::Empty => Box::new(std::iter::empty()),
+            FilterType::Error => failed(Error::Raised(data)),
+            FilterType::Member(idx) => single_path(descend(path, data, Data::Integer(*idx as i64))),
+            FilterType::Entry(name) => single_path(descend(path, data, Data::String(name.clone()))),
+            FilterType::Range(start, end) => {
+                let end = if *end > 0 {
+                    Data::Integer(*end as i64)
+                } else {
+                    Data::Null
+                };
+                let key = slice_key(Data::Integer(*start as i64), end);
+                single_path(descend(path, data, key))
+            }
+            FilterType::Array => {
+                let members: Vec<(Data, Data)> = match data {
+                    Data::Array(arr) => arr
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, member)| (Data::Integer(idx as i64), member))
+                        .collect(),
+                    Data::Hash(map) => map
+                        .into_iter()
+                        .map(|(key, value)| (Data::String(key), value))
+                        .collect(),
+                    _ => {
+                        return failed(Error::Filter(format!(
+                            "Cannot iterate over {}",
+                            data.type_name()
+                        )))
+                    }
+                };
+                Box::new(members.into_iter().map(move |(key, member)| {
+                    let mut path = path.clone();
+                    path.push(key);
+                    Ok((path, member))
+                }))
+            }
+            FilterType::Recurse => Box::new(DescendantPaths {
+                stack: vec![(path, data)],
+            }),
+            _ => invalid(self.apply(data)),
+        }
+    }
+}
+
+fn single_path(result: Result<(Vec<Data>, Data), Error>) -> Paths {
+    Box::new(std::iter::once(result))
+}
+
+/// Depth-first walk over a value and its members, along with their paths.
+struct DescendantPaths {
+    stack: Vec<(Vec<Data>, Data)>,
+}
+
+impl Iterator for DescendantPaths {
+    type Item = Result<(Vec<Data>, Data), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, data) = self.stack.pop()?;
+        let child = |key: Data, value: &Data| {
+            let mut path = path.clone();
+            path.push(key);
+            (path, value.clone())
+        };
+        match &data {
+            Data::Array(arr) => {
+                for (idx, member) in arr.iter().enumerate().rev() {
+                    self.stack.push(child(Data::Integer(idx as i64), member));
+                }
+            }
+            Data::Hash(map) => {
+                for (key, value) in map.iter().rev() {
+                    self.stack.push(child(Data::String(key.clone()), value));
+                }
+            }
+            _ => {}
+        }
+        Some(Ok((path, data)))
+    }
+}
+
+/// Applies `rhs` in path mode to every output of `lhs`.
+fn pipe_paths(lhs: Paths, rhs: Rc<FilterExpression>, env: &Env) -> Paths {
+    let env = env.clone();
+    Box::new(lhs.flat_map(move |result| match result {
+        Ok((path, data)) => rhs.paths(path, data, &env),
+        Err(e) => failed(e),
+    }))
+}
+
+impl FilterExpression {
+    /// Like `eval`, tracking the path of every output within `data`, found at `path`.
+    /// Only filters that select parts of their input, such as `.a`, `.[]` or
+    /// `select(f)`, have paths; other outputs are errors.
+    pub fn paths(&self, path: Vec<Data>, data: Data, env: &Env) -> Paths {
+        match self {
+            FilterExpression::Filter(t) => t.paths(path, data),
+            FilterExpression::Chain(lhs, rhs) => {
+                pipe_paths(lhs.paths(path, data, env), rhs.clone(), env)
+            }
+            FilterExpression::Binary(Operator::Pipe, lhs, rhs) => {
+                pipe_paths(lhs.paths(path, data, env), rhs.clone(), env)
+            }
+            FilterExpression::Binary(Operator::Comma, lhs, rhs) => {
+                let (rhs, env) = (rhs.clone(), env.clone());
+                let first = lhs.paths(path.clone(), data.clone(), &env);
+                Box::new(
+                    first
+                        .chain(std::iter::once_with(move || rhs.paths(path, data, &env)).flatten()),
+                )
+            }
+            FilterExpression::Binary(Operator::Alternative, lhs, rhs) => {
+                let found = Rc::new(Cell::new(false));
+                let seen = found.clone();
+                let truthy = lhs
+                    .paths(path.clone(), data.clone(), env)
+                    .filter_map(move |result| match result {
+                        Ok((path, value)) if value.is_truthy() => {
+                            seen.set(true);
+                            Some(Ok((path, value)))
+                        }
+                        _ => None,
+                    });
+                let (rhs, env) = (rhs.clone(), env.clone());
+                let rest = std::iter::once_with(move || {
+                    if found.get() {
+                        Box::new(std::iter::empty())
+                    } else {
+                        rhs.paths(path, data, &env)
+                    }
+                });
+                Box::new(truthy.chain(rest.flatten()))
+            }
+            FilterExpression::Select(condition) => select_paths(condition, path, data, env),
+            FilterExpression::If(condition, then, otherwise) => {
+                let (then, otherwise, env) = (then.clone(), otherwise.clone(), env.clone());
+                Box::new(
+                    condition
+                        .eval(data.clone(), &env)
+                        .flat_map(move |result| match result {
+                            Ok(value) if value.is_truthy() => {
+                                then.paths(path.clone(), data.clone(), &env)
+                            }
+                            Ok(_) => match &otherwise {
+                                Some(otherwise) => {
+                                    otherwise.paths(path.clone(), data.clone(), &env)
+                                }
+                                None => located(path.clone(), data.clone()),
+                            },
+                            Err(e) => failed(e),
+                        }),
+                )
+            }
+            FilterExpression::Bind(source, patterns, body) => {
+                let (patterns, body, env) = (patterns.clone(), body.clone(), env.clone());
+                Box::new(source.eval(data.clone(), &env).flat_map(move |result| {
+                    let envs = result.and_then(|value| bind_first(&patterns, value, &env));
+                    let (path, data, body) = (path.clone(), data.clone(), body.clone());
+                    match envs {
+                        Ok(envs) => Box::new(
+                            envs.into_iter()
+                                .flat_map(move |env| body.paths(path.clone(), data.clone(), &env)),
+                        ) as Paths,
+                        Err(e) => failed(e),
+                    }
+                }))
+            }
+            FilterExpression::Define(function, rest) => {
+                rest.paths(path, data, &env.define(function.clone()))
+            }
+            FilterExpression::Index(target, key) => {
+                let (target, env) = (target.clone(), env.clone());
+                Box::new(key.eval(data.clone(), &env).flat_map(move |key| match key {
+                    Ok(key) => Box::new(target.paths(path.clone(), data.clone(), &env).map(
+                        move |result| {
+                            let (path, value) = result?;
+                            descend(path, value, key.clone())
+                        },
+                    )) as Paths,
+                    Err(e) => failed(e),
+                }))
+            }
+            FilterExpression::Slice(target, from, to) => {
+                let (target, env) = (target.clone(), env.clone());
+                let bound =
+                    |bound: &Option<Rc<FilterExpression>>, data: Data, env: &Env| match bound {
+                        Some(bound) => bound.eval(data, env),
+                        None => single(Ok(Data::Null)),
+                    };
+                let to = to.clone();
+                Box::new(bound(from, data.clone(), &env).flat_map(move |from| {
+                    let from = match from {
+                        Ok(from) => from,
+                        Err(e) => return failed(e),
+                    };
+                    let (target, path, data, env) =
+                        (target.clone(), path.clone(), data.clone(), env.clone());
+                    Box::new(bound(&to, data.clone(), &env).flat_map(move |to| {
+                        let key = match to {
+                            Ok(to) => slice_key(from.clone(), to),
+                            Err(e) => return failed(e),
+                        };
+                        Box::new(target.paths(path.clone(), data.clone(), &env).map(
+                            move |result| {
+                                let (path, value) = result?;
+                                descend(path, value, key.clone())
+                            },
+                        )) as Paths
+                    })) as Paths
+                }))
+            }
+            FilterExpression::Recurse(step, condition) => {
+                recurse_paths(step.clone(), condition.clone(), path, data, env.clone())
+            }
+            FilterExpression::Try(body, handler) => {
+                let handler = handler.clone().map(|handler| {
+                    let env = env.clone();
+                    Box::new(move |e: Error| invalid(handler.eval(e.value(), &env)))
+                        as CatchHandler<(Vec<Data>, Data)>
+                });
+                Box::new(Catch::new(body.paths(path, data, env), handler))
+            }
+            FilterExpression::Call(name, args) => match env.function(name, args.len()) {
+                Ok(Callable::Function(function, scope)) => {
+                    function.call_paths(args, path, data, scope, env)
+                }
+                Ok(Callable::Closure(body, closure)) => body.paths(path, data, &closure),
+                Err(e) => failed(e),
+            },
+            FilterExpression::Builtin(Builtin::GetPath, args) => {
+                Box::new(args[0].eval(data.clone(), env).flat_map(move |result| {
+                    let mut path = path.clone();
+                    match result.and_then(as_path) {
+                        Ok(keys) => {
+                            path.extend(keys.iter().cloned());
+                            single_path(getpath(&data, &keys).map(|value| (path, value)))
+                        }
+                        Err(e) => failed(e),
+                    }
+                }))
+            }
+            _ => invalid(self.eval(data, env)),
+        }
+    }
+}
+
+/// Binds the first of the `?//` alternative patterns that fits the value.
+fn bind_first(patterns: &[Pattern], value: Data, env: &Env) -> Result<Vec<Env>, Error> {
+    let mut env = env.clone();
+    for name in patterns.iter().flat_map(Pattern::variables) {
+        env = env.bind(&name, Data::Null);
+    }
+    let mut result = Ok(Vec::new());
+    for pattern in patterns {
+        result = pattern.bind(value.clone(), &env);
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+/// The path of the value once for every truthy output of `condition`, like `select`.
+fn select_paths(condition: &FilterExpression, path: Vec<Data>, data: Data, env: &Env) -> Paths {
+    Box::new(
+        condition
+            .eval(data.clone(), env)
+            .filter_map(move |result| match result {
+                Ok(value) if value.is_truthy() => Some(Ok((path.clone(), data.clone()))),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }),
+    )
+}
+
+fn recurse_paths(
+    step: Rc<FilterExpression>,
+    condition: Option<Rc<FilterExpression>>,
+    path: Vec<Data>,
+    data: Data,
+    env: Env,
+) -> Paths {
+    let current = located(path.clone(), data.clone());
+    let next = std::iter::once_with(move || {
+        let mut children = step.paths(path, data, &env);
+        if let Some(condition) = &condition {
+            children = Box::new(children.flat_map({
+                let (condition, env) = (condition.clone(), env.clone());
+                move |result| match result {
+                    Ok((path, value)) => select_paths(&condition, path, value, &env),
+                    Err(e) => failed(e),
+                }
+            }));
+        }
+        Box::new(children.flat_map(move |result| match result {
+            Ok((path, value)) => {
+                recurse_paths(step.clone(), condition.clone(), path, value, env.clone())
+            }
+            Err(e) => failed(e),
+        })) as Paths
+    });
+    Box::new(current.chain(next.flatten()))
+}
+
+/// Checks that a path is an array of keys.
+pub fn as_path(path: Data) -> Result<Vec<Data>, Error> {
+    match path {
+        Data::Array(keys) => Ok(keys),
+        _ => Err(Error::Filter(
+            "Path must be specified as an array".to_string(),
+        )),
+    }
+}
+
+/// The value at `path` within `data`, or null if some part of the path is missing.
+pub fn getpath(data: &Data, path: &[Data]) -> Result<Data, Error> {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(data.clone()),
+    };
+    match (data, key) {
+        (Data::Null, _) => Ok(Data::Null),
+        (Data::Hash(map), Data::String(name)) => {
+            getpath(map.get(name).unwrap_or(&Data::Null), rest)
+        }
+        (Data::Array(arr), Data::Integer(_) | Data::Real(_)) => {
+            let member = position(key, arr.len())
+                .and_then(|idx| usize::try_from(idx).ok())
+                .and_then(|idx| arr.get(idx));
+            getpath(member.unwrap_or(&Data::Null), rest)
+        }
+        _ => getpath(&member(data.clone(), key)?, rest),
+    }
+}
+
+fn cannot_index(data: &Data, key: &Data) -> Error {
+    match key {
+        Data::String(name) => Error::Filter(format!(
+            "Cannot index {} with \"{}\"",
+            data.type_name(),
+            name
+        )),
+        _ => Error::Filter(format!(
+            "Cannot index {} with {}",
+            data.type_name(),
+            key.type_name()
+        )),
+    }
+}
+
+/// Position in an array of `len` members for a numeric path component,
+/// counting negative ones from the end.
+fn position(key: &Data, len: usize) -> Option<i64> {
+    let idx = match key {
+        Data::Integer(idx) => *idx,
+        Data::Real(idx) => idx.value().floor() as i64,
+        _ => return None,
+    };
+    Some(if idx < 0 { idx + len as i64 } else { idx })
+}
+
+/// Replaces the value at `path` within `data`, creating any missing objects and arrays.
+pub fn setpath(data: Data, path: &[Data], value: Data) -> Result<Data, Error> {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(value),
+    };
+    match (data, key) {
+        (Data::Null, Data::String(_)) => setpath(Data::Hash(IndexMap::new()), path, value),
+        (Data::Null, Data::Integer(_) | Data::Real(_) | Data::Hash(_)) => {
+            setpath(Data::Array(Vec::new()), path, value)
+        }
+        (Data::Hash(mut map), Data::String(name)) => {
+            let child = map.get(name).cloned().unwrap_or(Data::Null);
+            map.insert(name.clone(), setpath(child, rest, value)?);
+            Ok(Data::Hash(map))
+        }
+        (Data::Array(mut arr), Data::Integer(_) | Data::Real(_)) => {
+            let idx = position(key, arr.len()).unwrap_or_default();
+            let idx = usize::try_from(idx)
+                .map_err(|_| Error::Filter("Out of bounds negative array index".to_string()))?;
+            if idx >= arr.len() {
+                arr.resize(idx + 1, Data::Null);
+            }
+            let child = std::mem::replace(&mut arr[idx], Data::Null);
+            arr[idx] = setpath(child, rest, value)?;
+            Ok(Data::Array(arr))
+        }
+        (Data::Array(mut arr), Data::Hash(slice)) => {
+            let (from, to) =
+                FilterType::slice_bounds(arr.len(), bound(slice, "start"), bound(slice, "end"))?;
+            let current = Data::Array(arr[from..to].to_vec());
+            match setpath(current, rest, value)? {
+                Data::Array(replacement) => {
+                    arr.splice(from..to, replacement);
+                    Ok(Data::Array(arr))
+                }
+                _ => Err(Error::Filter(
+                    "A slice of an array can only be assigned another array".to_string(),
+                )),
+            }
+        }
+        (data, key) => Err(cannot_index(&data, key)),
+    }
+}
+
+/// Removes the value at `path` from `data`; missing paths are left alone.
+fn delpath(data: Data, path: &[Data]) -> Result<Data, Error> {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(Data::Null),
+    };
+    match (data, key) {
+        (Data::Null, _) => Ok(Data::Null),
+        (Data::Hash(mut map), Data::String(name)) => {
+            if rest.is_empty() {
+                map.shift_remove(name);
+            } else if let Some(child) = map.get_mut(name) {
+                *child = delpath(std::mem::replace(child, Data::Null), rest)?;
+            }
+            Ok(Data::Hash(map))
+        }
+        (Data::Array(mut arr), Data::Integer(_) | Data::Real(_)) => {
+            let idx = position(key, arr.len()).unwrap_or_default();
+            if let Ok(idx) = usize::try_from(idx) {
+                if idx < arr.len() && rest.is_empty() {
+                    arr.remove(idx);
+                } else if idx < arr.len() {
+                    arr[idx] = delpath(std::mem::replace(&mut arr[idx], Data::Null), rest)?;
+                }
+            }
+            Ok(Data::Array(arr))
+        }
+        (Data::Array(mut arr), Data::Hash(slice)) => {
+            let (from, to) =
+                FilterType::slice_bounds(arr.len(), bound(slice, "start"), bound(slice, "end"))?;
+            if rest.is_empty() {
+                arr.drain(from..to);
+                return Ok(Data::Array(arr));
+            }
+            let current = Data::Array(arr[from..to].to_vec());
+            match delpath(current, rest)? {
+                Data::Array(replacement) => {
+                    arr.splice(from..to, replacement);
+                    Ok(Data::Array(arr))
+                }
+                _ => unreachable!("deleting within an array leaves an array"),
+            }
+        }
+        (data, key) => Err(cannot_index(&data, key)),
+    }
+}
+
+/// Removes the values at every one of `paths`, deleting the last ones first
+/// so that removing array members doesn't shift the positions of the others.
+pub fn delpaths(data: Data, mut paths: Vec<Data>) -> Result<Data, Error> {
+    paths.sort();
+    paths.dedup();
+    let mut data = data;
+    for path in paths.into_iter().rev() {
+        data = delpath(data, &as_path(path)?)?;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tracks_paths() {
+        let input = r#"{"a": [{"b": 1}, {"b": 2}], "c": null}"#;
+        assert_eq!(
+            run(
+                "path(.a[0].b), path(.a[]), path(.a[1:]), path(.c // .a)",
+                input
+            ),
+            vec![
+                r#"["a",0,"b"]"#,
+                r#"["a",0]"#,
+                r#"["a",1]"#,
+                r#"["a",{"start":1,"end":null}]"#,
+                r#"["a"]"#
+            ]
+        );
+        assert_eq!(
+            run("[path(.a[] | select(.b > 1))], [path(..)] | length", input),
+            vec!["1", "7"]
+        );
+        assert_eq!(
+            run(
+                r#"def f: .a; path(f[0]), path(getpath(["x", "y"])), path(.a[]?.x?)"#,
+                input
+            ),
+            vec![
+                r#"["a",0]"#,
+                r#"["x","y"]"#,
+                r#"["a",0,"x"]"#,
+                r#"["a",1,"x"]"#
+            ]
+        );
+        assert_eq!(
+            run("try path(1) catch .", "null"),
+            vec![r#""Invalid path expression with result 1""#]
+        );
+    }
+
+    #[test]
+    fn recurses_paths_like_values() {
+        let input = "[[1, 2], 3]";
+        let filter = r#".[]?; . != 2, (select(type == "array") | true), false"#;
+        assert_eq!(
+            run(&format!("[path(recurse({}))]", filter), input),
+            vec![r#"[[],[0],[0,0],[0],[0,0],[1]]"#]
+        );
+        assert_eq!(
+            run(
+                &format!(
+                    "[recurse({})] == [path(recurse({})) as $p | getpath($p)]",
+                    filter, filter
+                ),
+                input
+            ),
+            vec!["true"]
+        );
+    }
+
+    #[test]
+    fn lists_paths() {
+        let input = r#"{"a": [1, {"b": 2}], "c": "x"}"#;
+        assert_eq!(
+            run("[paths]", input),
+            vec![r#"[["a"],["a",0],["a",1],["a",1,"b"],["c"]]"#]
+        );
+        assert_eq!(
+            run("[leaf_paths]", input),
+            vec![r#"[["a",0],["a",1,"b"],["c"]]"#]
+        );
+        assert_eq!(
+            run("[paths(type == \"number\")]", input),
+            vec![r#"[["a",0],["a",1,"b"]]"#]
+        );
+    }
+
+    #[test]
+    fn gets_and_sets_paths() {
+        let input = r#"{"a": {"b": 1}, "l": [1, 2, 3]}"#;
+        assert_eq!(
+            run(
+                r#"getpath(["a", "b"]), getpath(["x", "y"]), getpath(["l", -1])"#,
+                input
+            ),
+            vec!["1", "null", "3"]
+        );
+        assert_eq!(
+            run(r#"setpath(["a", "c"]; 2) | .a"#, input),
+            vec![r#"{"b":1,"c":2}"#]
+        );
+        assert_eq!(
+            run(r#"setpath([2, "x"]; 1)"#, "null"),
+            vec![r#"[null,null,{"x":1}]"#]
+        );
+        assert_eq!(
+            run(
+                r#"setpath(["l", {"start": 1, "end": 2}]; ["x", "y"]) | .l"#,
+                input
+            ),
+            vec![r#"[1,"x","y",3]"#]
+        );
+        assert_eq!(
+            run(r#"try setpath(["a", "b", "c"]; 1) catch ."#, input),
+            vec![r#""Cannot index number with \"c\"""#]
+        );
+    }
+
+    #[test]
+    fn deletes_paths() {
+        let input = r#"{"a": {"b": 1, "c": 2}, "l": [1, 2, 3, 4]}"#;
+        assert_eq!(
+            run(r#"delpaths([["a", "b"], ["l", 0], ["l", 2]])"#, input),
+            vec![r#"{"a":{"c":2},"l":[2,4]}"#]
+        );
+        assert_eq!(run("del(.l[1:3], .a)", input), vec![r#"{"l":[1,4]}"#]);
+        assert_eq!(
+            run("del(.l[] | select(. % 2 == 0)) | .l", input),
+            vec!["[1,3]"]
+        );
+        assert_eq!(run("del(.)", input), vec!["null"]);
+    }
+
+    #[test]
+    fn picks_paths() {
+        let input = r#"{"a": {"b": 1, "c": 2}, "d": 3, "l": [1, 2]}"#;
+        assert_eq!(run("pick(.a.b, .d)", input), vec![r#"{"a":{"b":1},"d":3}"#]);
+        assert_eq!(run("pick(.l[1])", input), vec![r#"{"l":[null,2]}"#]);
+    }
+}
@@ -0,0 +1,146 @@
+use super::*;
+use crate::output::*;
+use std::rc::Rc;
+
+/// Destructuring pattern on the right-hand side of `as`.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Variable(String),
+    Array(Vec<Pattern>),
+    /// Object entries; only `$name` keys may omit the nested pattern, as in `{$name}`.
+    Object(Vec<(PatternKey, Option<Pattern>)>),
+}
+
+#[derive(Debug, Clone)]
+pub enum PatternKey {
+    /// `$name` both binds the variable and selects the `name` entry.
+    Variable(String),
+    Literal(String),
+    Expression(Rc<FilterExpression>),
+}
+
+impl Pattern {
+    /// Names of every variable the pattern binds, in order of appearance.
+    pub fn variables(&self) -> Vec<String> {
+        match self {
+            Pattern::Variable(name) => vec![name.to_string()],
+            Pattern::Array(members) => members.iter().flat_map(Pattern::variables).collect(),
+            Pattern::Object(entries) => entries
+                .iter()
+                .flat_map(|(key, pattern)| {
+                    let mut names = match key {
+                        PatternKey::Variable(name) => vec![name.to_string()],
+                        _ => Vec::new(),
+                    };
+                    names.extend(pattern.iter().flat_map(Pattern::variables));
+                    names
+                })
+                .collect(),
+        }
+    }
+
+    /// Binds the pattern's variables to the matching parts of `value`.
+    /// Key expressions with several outputs yield one scope for each.
+    pub fn bind(&self, value: Data, env: &Env) -> Result<Vec<Env>, Error> {
+        match self {
+            Pattern::Variable(name) => Ok(vec![env.bind(name, value)]),
+            Pattern::Array(members) => {
+                if !matches!(value, Data::Array(_) | Data::Null) {
+                    return Err(Error::Filter(format!(
+                        "Cannot index {} with number",
+                        value.type_name()
+                    )));
+                }
+                let mut envs = vec![env.clone()];
+                for (idx, pattern) in members.iter().enumerate() {
+                    let member = FilterType::index(value.clone(), &Data::Integer(idx as i64))?;
+                    envs = Pattern::bind_each(pattern, &member, envs)?;
+                }
+                Ok(envs)
+            }
+            Pattern::Object(entries) => {
+                let mut envs = vec![env.clone()];
+                for (key, pattern) in entries {
+                    let mut bound = Vec::new();
+                    for env in envs {
+                        let keys = match key {
+                            PatternKey::Variable(name) | PatternKey::Literal(name) => {
+                                vec![Data::String(name.to_string())]
+                            }
+                            PatternKey::Expression(expr) => expr
+                                .eval(value.clone(), &env)
+                                .collect::<Result<Vec<Data>, Error>>()?,
+                        };
+                        for key_value in keys {
+                            if !matches!(key_value, Data::String(_)) {
+                                return Err(Error::Filter(format!(
+                                    "Cannot index {} with {}",
+                                    value.type_name(),
+                                    key_value.type_name()
+                                )));
+                            }
+                            let entry = FilterType::index(value.clone(), &key_value)?;
+                            let env = match key {
+                                PatternKey::Variable(name) => env.bind(name, entry.clone()),
+                                _ => env.clone(),
+                            };
+                            match pattern {
+                                Some(pattern) => bound.extend(pattern.bind(entry, &env)?),
+                                None => bound.push(env),
+                            }
+                        }
+                    }
+                    envs = bound;
+                }
+                Ok(envs)
+            }
+        }
+    }
+
+    fn bind_each(pattern: &Pattern, value: &Data, envs: Vec<Env>) -> Result<Vec<Env>, Error> {
+        let mut bound = Vec::new();
+        for env in envs {
+            bound.extend(pattern.bind(value.clone(), &env)?);
+        }
+        Ok(bound)
+    }
+}
+
+/// Whether `name` can be written as a bare object key.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Pattern::Variable(name) => write!(f, "${}", name),
+            Pattern::Array(members) => {
+                let members: Vec<String> = members.iter().map(|p| p.to_string()).collect();
+                write!(f, "[{}]", members.join(","))
+            }
+            Pattern::Object(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, pattern)| {
+                        let key = match key {
+                            PatternKey::Variable(name) => format!("${}", name),
+                            PatternKey::Literal(name) if is_identifier(name) => name.to_string(),
+                            PatternKey::Literal(name) => {
+                                to_json(&Data::String(name.to_string()), JsonStyle::Compact)
+                            }
+                            PatternKey::Expression(expr) => format!("({})", expr),
+                        };
+                        match pattern {
+                            Some(pattern) => format!("{}:{}", key, pattern),
+                            None => key,
+                        }
+                    })
+                    .collect();
+                write!(f, "{{{}}}", entries.join(","))
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use super::*;
+use indexmap::IndexMap;
+use regex::{Captures, Regex, RegexBuilder};
+
+/// Regular expression compiled with jq's flags:
+///
+/// - `g` finds every match instead of only the first one
+/// - `i` ignores case
+/// - `x` ignores whitespace and `#` comments in the expression
+/// - `n` ignores empty matches
+/// - `s` lets `.` match newlines
+///
+/// jq's `l` (longest match) flag is rejected, as matches are always leftmost-first here.
+pub struct Matcher {
+    regex: Regex,
+    global: bool,
+    skip_empty: bool,
+}
+
+fn string<'a>(data: &'a Data, what: &str) -> Result<&'a str, Error> {
+    match data {
+        Data::String(s) => Ok(s),
+        _ => Err(Error::Filter(format!(
+            "{} cannot be {}, as it is not a string",
+            describe(data),
+            what
+        ))),
+    }
+}
+
+/// Position of the byte offset `idx` in codepoints, which is how jq reports offsets.
+fn offset(s: &str, idx: usize) -> Data {
+    Data::Integer(s[..idx].chars().count() as i64)
+}
+
+impl Matcher {
+    pub fn new(re: &Data, flags: &Data) -> Result<Self, Error> {
+        let re = string(re, "used as a regex")?;
+        let flags = match flags {
+            Data::Null => "",
+            _ => string(flags, "used as regex flags")?,
+        };
+        let mut builder = RegexBuilder::new(re);
+        let (mut global, mut skip_empty) = (false, false);
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' => _ = builder.case_insensitive(true),
+                'x' => _ = builder.ignore_whitespace(true),
+                'n' => skip_empty = true,
+                's' => _ = builder.dot_matches_new_line(true),
+                _ => {
+                    return Err(Error::Filter(format!(
+                        "{} is not a valid modifier string",
+                        flags
+                    )))
+                }
+            }
+        }
+        let regex = builder
+            .build()
+            .map_err(|e| Error::Filter(format!("{} is not a valid regex: {}", re, e)))?;
+        Ok(Matcher {
+            regex,
+            global,
+            skip_empty,
+        })
+    }
+
+    /// Finds every match, whatever the flags say.
+    pub fn global(mut self) -> Self {
+        self.global = true;
+        self
+    }
+
+    fn captures<'a>(&self, s: &'a str) -> Vec<Captures<'a>> {
+        let found = self
+            .regex
+            .captures_iter(s)
+            .filter(|captures| !self.skip_empty || !captures[0].is_empty());
+        if self.global {
+            found.collect()
+        } else {
+            found.take(1).collect()
+        }
+    }
+
+    pub fn test(&self, data: &Data) -> Result<Data, Error> {
+        let s = string(data, "matched")?;
+        Ok(Data::Boolean(!self.captures(s).is_empty()))
+    }
+
+    /// `match`: an object for every match, describing it and its capture groups.
+    pub fn matches(&self, data: &Data) -> Result<Vec<Data>, Error> {
+        let s = string(data, "matched")?;
+        Ok(self
+            .captures(s)
+            .iter()
+            .map(|captures| {
+                let whole = captures.get(0).expect("a match always has the whole group");
+                let groups = self
+                    .regex
+                    .capture_names()
+                    .enumerate()
+                    .skip(1)
+                    .map(|(idx, name)| {
+                        let mut group = IndexMap::new();
+                        match captures.get(idx) {
+                            Some(m) => {
+                                group.insert("offset".to_string(), offset(s, m.start()));
+                                group.insert(
+                                    "length".to_string(),
+                                    Data::Integer(m.as_str().chars().count() as i64),
+                                );
+                                group.insert(
+                                    "string".to_string(),
+                                    Data::String(m.as_str().to_string()),
+                                );
+                            }
+                            None => {
+                                group.insert("offset".to_string(), Data::Integer(-1));
+                                group.insert("length".to_string(), Data::Integer(0));
+                                group.insert("string".to_string(), Data::Null);
+                            }
+                        }
+                        let name = name.map_or(Data::Null, |name| Data::String(name.to_string()));
+                        group.insert("name".to_string(), name);
+                        Data::Hash(group)
+                    })
+                    .collect();
+                let mut object = IndexMap::new();
+                object.insert("offset".to_string(), offset(s, whole.start()));
+                object.insert(
+                    "length".to_string(),
+                    Data::Integer(whole.as_str().chars().count() as i64),
+                );
+                object.insert(
+                    "string".to_string(),
+                    Data::String(whole.as_str().to_string()),
+                );
+                object.insert("captures".to_string(), Data::Array(groups));
+                Data::Hash(object)
+            })
+            .collect())
+    }
+
+    fn named(&self, captures: &Captures) -> Data {
+        let mut named = IndexMap::new();
+        for name in self.regex.capture_names().flatten() {
+            let value = captures
+                .name(name)
+                .map_or(Data::Null, |m| Data::String(m.as_str().to_string()));
+            named.insert(name.to_string(), value);
+        }
+        Data::Hash(named)
+    }
+
+    /// `capture`: an object of the named groups for every match.
+    pub fn capture(&self, data: &Data) -> Result<Vec<Data>, Error> {
+        let s = string(data, "matched")?;
+        Ok(self
+            .captures(s)
+            .iter()
+            .map(|captures| self.named(captures))
+            .collect())
+    }
+
+    /// `scan`: every matched string, or the array of its groups if there are any.
+    pub fn scan(&self, data: &Data) -> Result<Vec<Data>, Error> {
+        let s = string(data, "matched")?;
+        Ok(self
+            .captures(s)
+            .iter()
+            .map(|captures| {
+                if captures.len() == 1 {
+                    return Data::String(captures[0].to_string());
+                }
+                Data::Array(
+                    captures
+                        .iter()
+                        .skip(1)
+                        .map(|m| m.map_or(Data::Null, |m| Data::String(m.as_str().to_string())))
+                        .collect(),
+                )
+            })
+            .collect())
+    }
+
+    /// `split`: the parts of the string between matches.
+    pub fn split(&self, data: &Data) -> Result<Vec<Data>, Error> {
+        let s = string(data, "split")?;
+        let mut parts = Vec::new();
+        let mut last = 0;
+        for captures in self.captures(s) {
+            let m = captures.get(0).expect("a match always has the whole group");
+            parts.push(Data::String(s[last..m.start()].to_string()));
+            last = m.end();
+        }
+        parts.push(Data::String(s[last..].to_string()));
+        Ok(parts)
+    }
+
+    /// `sub`: replaces matches with the outputs of `replacement`, applied to an object of
+    /// the named groups. Several outputs give one string for every combination of them.
+    pub fn substitute(
+        &self,
+        data: &Data,
+        replacement: &FilterExpression,
+        env: &Env,
+    ) -> Result<Vec<Data>, Error> {
+        let s = string(data, "matched")?;
+        let mut results = vec![String::new()];
+        let mut last = 0;
+        for captures in self.captures(s) {
+            let m = captures.get(0).expect("a match always has the whole group");
+            let prefix = &s[last..m.start()];
+            let outputs = replacement
+                .eval(self.named(&captures), env)
+                .map(|output| match output? {
+                    Data::String(output) => Ok(output),
+                    output => Err(Error::Filter(format!(
+                        "{} cannot be added to a string",
+                        describe(&output)
+                    ))),
+                })
+                .collect::<Result<Vec<String>, Error>>()?;
+            results = results
+                .iter()
+                .flat_map(|result| {
+                    outputs
+                        .iter()
+                        .map(move |output| format!("{}{}{}", result, prefix, output))
+                })
+                .collect();
+            last = m.end();
+        }
+        Ok(results
+            .into_iter()
+            .map(|result| Data::String(result + &s[last..]))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tests_strings() {
+        let input = r#"["ann@zidant.com", "bob@example.com", "CAT@ZIDANT.COM"]"#;
+        assert_eq!(
+            run(r#"map(select(test("@zidant\\.com$")))"#, input),
+            vec![r#"["ann@zidant.com"]"#]
+        );
+        assert_eq!(
+            run(
+                r#"map(select(test("@zidant\\.com$"; "i"))) | length"#,
+                input
+            ),
+            vec!["2"]
+        );
+        assert_eq!(
+            run(r#"test("a b # comment"; "x")"#, r#""ab""#),
+            vec!["true"]
+        );
+        assert_eq!(
+            run(r#"test("a.b"), test("a.b"; "s")"#, "\"a\\nb\""),
+            vec!["false", "true"]
+        );
+        for flags in ["q", "l", "gl"] {
+            assert!(
+                Matcher::new(
+                    &Data::String("a".to_string()),
+                    &Data::String(flags.to_string())
+                )
+                .is_err(),
+                "{} should not be a valid modifier string",
+                flags
+            );
+        }
+        assert!(Matcher::new(&Data::String("(".to_string()), &Data::Null).is_err());
+    }
+
+    #[test]
+    fn describes_matches() {
+        assert_eq!(
+            run(r#"match("(?<area>\\d{3})-(\\d+)?")"#, r#""tél: 555-""#),
+            vec![concat!(
+                r#"{"offset":5,"length":4,"string":"555-","captures":["#,
+                r#"{"offset":5,"length":3,"string":"555","name":"area"},"#,
+                r#"{"offset":-1,"length":0,"string":null,"name":null}]}"#
+            )]
+        );
+        assert_eq!(
+            run(r#"[match("a"; "g").offset]"#, r#""banana""#),
+            vec!["[1,3,5]"]
+        );
+        assert_eq!(run(r#"[match(""; "gn")]"#, r#""ab""#), vec!["[]"]);
+    }
+
+    #[test]
+    fn captures_named_groups() {
+        let input = r#"["(555) 123-4567", "(212) 555-0000"]"#;
+        assert_eq!(
+            run(
+                r#".[] | capture("\\((?<area>\\d+)\\) (?<rest>.*)") | .area"#,
+                input
+            ),
+            vec![r#""555""#, r#""212""#]
+        );
+        assert_eq!(
+            run(r#"[scan("\\d+")], [scan("(a)(x)?")]"#, r#""a1b22""#),
+            vec![r#"["1","22"]"#, r#"[["a",null]]"#]
+        );
+    }
+
+    #[test]
+    fn splits_and_substitutes() {
+        let input = r#""a, b,c""#;
+        assert_eq!(
+            run(r#"split(", *"; null)"#, input),
+            vec![r#"["a","b","c"]"#]
+        );
+        assert_eq!(run(r#"[splits(",")]"#, input), vec![r#"["a"," b","c"]"#]);
+        assert_eq!(run(r#"sub(","; ";")"#, input), vec![r#""a; b,c""#]);
+        assert_eq!(run(r#"gsub(", *"; "-")"#, input), vec![r#""a-b-c""#]);
+        assert_eq!(
+            run(r#"sub("B"; "x"; "gi")"#, r#""abcb""#),
+            vec![r#""axcx""#]
+        );
+        assert_eq!(
+            run(r#"gsub("(?<d>\\d)"; "<\(.d)>")"#, r#""a1b2""#),
+            vec![r#""a<1>b<2>""#]
+        );
+        assert_eq!(
+            run(r#"sub("b"; "1", "2")"#, r#""abc""#),
+            vec![r#""a1c""#, r#""a2c""#]
+        );
+    }
+}
@@ -1,27 +1,40 @@
 use super::*;
+use crate::output::*;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum FilterType {
     Current,
+    Empty,
+    Not,
     Array,
     Keys,
+    KeysUnsorted,
     Member(usize),
     Entry(String),
     Range(usize, usize),
+    /// `@name` string formatter, such as `@base64`.
+    Format(String),
+    /// `error`: raises the input as an error.
+    Error,
+    /// `..` or `recurse`: the input and every value nested in it, in document order.
+    Recurse,
 }
 
 impl Filterable for FilterType {
-    fn get_filterables(&self) -> Vec<Box<dyn Filterable>> {
-        Vec::new()
-    }
-    fn apply(&self, data: Data) -> Result<Data, Error> {
+    fn apply(&self, data: Data) -> Outputs {
         match &self {
-            FilterType::Current => Ok(data),
+            FilterType::Current => single(Ok(data)),
+            FilterType::Empty => none(),
+            FilterType::Not => single(Ok(Data::Boolean(!data.is_truthy()))),
             FilterType::Array => self.array(data),
-            FilterType::Range(start, end) => self.range(data, *start, *end),
-            FilterType::Keys => self.keys(data),
-            FilterType::Member(idx) => self.member(data, *idx),
-            FilterType::Entry(name) => self.entry(data, name.to_string()),
+            FilterType::Range(start, end) => single(self.range(data, *start, *end)),
+            FilterType::Keys => single(self.keys(data, true)),
+            FilterType::KeysUnsorted => single(self.keys(data, false)),
+            FilterType::Member(idx) => single(self.member(data, *idx)),
+            FilterType::Entry(name) => single(self.entry(data, name.to_string())),
+            FilterType::Format(name) => single(format(name, &data).map(Data::String)),
+            FilterType::Error => single(Err(Error::Raised(data))),
+            FilterType::Recurse => Box::new(Descendants { stack: vec![data] }),
         }
     }
 }
@@ -30,25 +43,92 @@ impl std::fmt::Display for FilterType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         match self {
             FilterType::Current => write!(f, "."),
-            FilterType::Array => write!(f, "[]"),
+            FilterType::Empty => write!(f, "empty"),
+            FilterType::Not => write!(f, "not"),
+            FilterType::Array => write!(f, ".[]"),
             FilterType::Keys => write!(f, "keys"),
-            FilterType::Member(n) => write!(f, "[{}]", n),
-            FilterType::Entry(n) => write!(f, "[\"{}\"]", n),
-            FilterType::Range(m, n) => write!(f, "[{}:{}]", m, n),
+            FilterType::KeysUnsorted => write!(f, "keys_unsorted"),
+            FilterType::Member(n) => write!(f, ".[{}]", n),
+            FilterType::Entry(n) if is_identifier(n) => write!(f, ".{}", n),
+            FilterType::Entry(n) => write!(
+                f,
+                ".{}",
+                to_json(&Data::String(n.to_string()), JsonStyle::Compact)
+            ),
+            FilterType::Range(m, n) => write!(f, ".[{}:{}]", m, n),
+            FilterType::Format(name) => write!(f, "@{}", name),
+            FilterType::Error => write!(f, "error"),
+            FilterType::Recurse => write!(f, ".."),
         }
     }
 }
 
+/// Depth-first walk over a value and its members, visiting parents before children.
+struct Descendants {
+    stack: Vec<Data>,
+}
+
+impl Iterator for Descendants {
+    type Item = Result<Data, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.stack.pop()?;
+        match &data {
+            Data::Array(arr) => self.stack.extend(arr.iter().rev().cloned()),
+            Data::Hash(map) => self.stack.extend(map.values().rev().cloned()),
+            _ => {}
+        }
+        Some(Ok(data))
+    }
+}
+
 impl FilterType {
-    fn array(&self, data: Data) -> Result<Data, Error> {
-        if let Data::Array(arr) = data {
-            Ok(Data::Array(arr))
-        } else {
-            Err(Error::Filter)
+    /// Whether the filter is written in brackets, so it can directly follow another term.
+    pub fn is_bracketed(&self) -> bool {
+        matches!(
+            self,
+            FilterType::Array | FilterType::Member(_) | FilterType::Range(_, _)
+        )
+    }
+
+    /// Indexes `data` with a computed key, as in destructuring or `.[$k]`.
+    /// Negative numbers index arrays from the end.
+    pub fn index(data: Data, key: &Data) -> Result<Data, Error> {
+        match key {
+            Data::String(name) => FilterType::Entry(name.to_string()).entry(data, name.to_string()),
+            Data::Integer(idx) => FilterType::position(data, *idx),
+            Data::Real(idx) if idx.value().is_nan() => Ok(Data::Null),
+            Data::Real(idx) => FilterType::position(data, idx.value().floor() as i64),
+            _ => Err(Error::Filter(format!(
+                "Cannot index {} with {}",
+                data.type_name(),
+                key.type_name()
+            ))),
         }
     }
 
-    fn keys(&self, data: Data) -> Result<Data, Error> {
+    fn array(&self, data: Data) -> Outputs {
+        match data {
+            Data::Array(arr) => Box::new(arr.into_iter().map(Ok)),
+            Data::Hash(map) => Box::new(map.into_values().map(Ok)),
+            _ => single(Err(Error::Filter(format!(
+                "Cannot iterate over {}",
+                data.type_name()
+            )))),
+        }
+    }
+
+    fn position(data: Data, idx: i64) -> Result<Data, Error> {
+        let idx = match &data {
+            Data::Array(arr) if idx < 0 => idx + arr.len() as i64,
+            _ => idx,
+        };
+        // Indices still negative are out of range, like any index past the end.
+        let idx = usize::try_from(idx).unwrap_or(usize::MAX);
+        FilterType::Member(idx).member(data, idx)
+    }
+
+    fn keys(&self, data: Data, sorted: bool) -> Result<Data, Error> {
         match data {
             Data::Array(arr) => {
                 let mut list: Vec<Data> = Vec::new();
@@ -60,67 +140,89 @@ impl FilterType {
                 Ok(Data::Array(list))
             }
             Data::Hash(map) => {
+                let mut names: Vec<&String> = map.keys().collect();
+                if sorted {
+                    names.sort();
+                }
                 let mut keys: Vec<Data> = Vec::new();
-                for key in map.keys() {
+                for key in names {
                     keys.push(Data::String(key.to_string()));
                 }
                 Ok(Data::Array(keys))
             }
 
-            _ => Err(Error::Filter),
+            _ => Err(Error::Filter(format!("{} has no keys", data.type_name()))),
         }
     }
 
     fn member(&self, data: Data, idx: usize) -> Result<Data, Error> {
-        if let Data::Array(arr) = data {
-            if idx < arr.len() {
-                Ok(arr[idx].clone())
-            } else {
-                Err(Error::Filter)
-            }
-        } else {
-            Err(Error::Filter)
+        match data {
+            Data::Array(arr) => Ok(arr.get(idx).cloned().unwrap_or(Data::Null)),
+            Data::Null => Ok(Data::Null),
+            _ => Err(Error::Filter(format!(
+                "Cannot index {} with number",
+                data.type_name()
+            ))),
         }
     }
 
     fn entry(&self, data: Data, name: String) -> Result<Data, Error> {
         match data {
-            Data::Hash(map) => {
-                if map.contains_key(&name) {
-                    Ok(map.get(&name).unwrap().clone())
-                } else {
-                    Err(Error::Filter)
-                }
-            }
-            Data::Array(arr) => {
-                let mut new_data: Vec<Data> = Vec::new();
-                for val in arr {
-                    if let Data::Hash(obj) = val {
-                        if obj.contains_key(&name) {
-                            new_data.push(obj.get(&name).unwrap().clone());
-                        }
-                    }
-                }
-                Ok(Data::Array(new_data))
-            }
-            _ => Err(Error::Filter),
+            Data::Hash(map) => Ok(map.get(&name).cloned().unwrap_or(Data::Null)),
+            Data::Null => Ok(Data::Null),
+            _ => Err(Error::Filter(format!(
+                "Cannot index {} with \"{}\"",
+                data.type_name(),
+                name
+            ))),
         }
     }
 
     fn range(&self, data: Data, start: usize, end: usize) -> Result<Data, Error> {
-        if let Data::Array(arr) = data {
-            let mut list: Vec<Data> = Vec::new();
-            let mut idx = 0;
-            let end_corr = if end > 0 { end } else { arr.len() };
-            while idx < arr.len() {
-                if idx >= start && idx < end_corr {
-                    list.push(arr[idx].clone());
-                }
-                idx += 1;
-            }
-            Ok(Data::Array(list))
+        let end = if end > 0 {
+            Data::Integer(end as i64)
         } else {
-            Err(Error::Filter)
-        }
+            Data::Null
+        };
+        FilterType::slice(data, &Data::Integer(start as i64), &end)
+    }
+
+    /// Slices an array or string between computed bounds, as in `.[$from:$to]`.
+    /// Null bounds are open and negative ones count from the end.
+    pub fn slice(data: Data, from: &Data, to: &Data) -> Result<Data, Error> {
+        let len = match &data {
+            Data::Null => return Ok(Data::Null),
+            Data::Array(arr) => arr.len(),
+            Data::String(s) => s.chars().count(),
+            _ => return Err(Error::Filter(format!("Cannot slice {}", data.type_name()))),
+        };
+        let (from, to) = FilterType::slice_bounds(len, from, to)?;
+        Ok(match data {
+            Data::Array(arr) => Data::Array(arr[from..to].to_vec()),
+            Data::String(s) => Data::String(s.chars().skip(from).take(to - from).collect()),
+            _ => unreachable!(),
+        })
+    }
+
+    /// Resolves slice bounds against a length of `len`, as positions `from..to`.
+    pub fn slice_bounds(len: usize, from: &Data, to: &Data) -> Result<(usize, usize), Error> {
+        let bound = |bound: &Data, default: usize, round: fn(f64) -> f64| -> Result<usize, Error> {
+            let n = match bound {
+                Data::Null => return Ok(default),
+                Data::Integer(n) => *n as f64,
+                Data::Real(n) => round(n.value()),
+                _ => {
+                    return Err(Error::Filter(format!(
+                        "Start and end indices of a slice must be numbers, got {}",
+                        bound.type_name()
+                    )))
+                }
+            };
+            let n = if n < 0.0 { n + len as f64 } else { n };
+            Ok(n.clamp(0.0, len as f64) as usize)
+        };
+        let from = bound(from, 0, f64::floor)?;
+        let to = bound(to, len, f64::ceil)?.max(from);
+        Ok((from, to))
     }
 }
@@ -3,23 +3,81 @@ use std::iter::Peekable;
 
 use crate::error::*;
 
+/// Reserved words that structure expressions and can't be used as function names.
+const KEYWORDS: [&str; 11] = [
+    "if", "then", "elif", "else", "end", "as", "def", "reduce", "foreach", "try", "catch",
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     OpenBracket,
     CloseBracket,
+    OpenParen,
+    CloseParen,
+    OpenBrace,
+    CloseBrace,
     Number(String),
     Word(String),
+    Keyword(String),
+    /// String with `\(...)` interpolations.
+    Template(Vec<StringPart>),
+    /// Object field access written as `.name`.
+    Field(String),
+    /// Variable reference written as `$name`.
+    Variable(String),
+    /// String formatter written as `@name`.
+    Format(String),
     Str(String),
     Dot,
+    /// Recursive descent `..`.
+    DoubleDot,
     Bar,
     Comma,
     Colon,
+    Semicolon,
+    Null,
+    Boolean(bool),
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    DoubleSlash,
+    /// Assignment operators `=`, `|=`, `+=`, `-=`, `*=`, `/=`, `%=` and `//=`.
+    Assign,
+    BarAssign,
+    PlusAssign,
+    MinusAssign,
+    StarAssign,
+    SlashAssign,
+    PercentAssign,
+    DoubleSlashAssign,
+    Question,
+    /// Destructuring alternative `?//`.
+    Alternatives,
+}
+
+/// Part of an interpolated string: literal text, or the tokens of an interpolated expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Literal(String),
+    Tokens(Vec<Token>),
 }
 
 #[derive(Debug)]
 pub struct Lexer<Chars: Iterator<Item = char>> {
     source: Peekable<Chars>,
     next_token: Option<Token>,
+    /// Token read ahead while looking for a longer one, as the `/` in `?/`.
+    pending: Option<Token>,
 }
 
 impl Display for Token {
@@ -27,13 +85,50 @@ impl Display for Token {
         let kind = match &self {
             Token::OpenBracket => "open bracket".to_string(),
             Token::CloseBracket => "close bracket".to_string(),
+            Token::OpenParen => "open paren".to_string(),
+            Token::CloseParen => "close paren".to_string(),
+            Token::OpenBrace => "open brace".to_string(),
+            Token::CloseBrace => "close brace".to_string(),
             Token::Dot => "dot".to_string(),
+            Token::DoubleDot => "..".to_string(),
             Token::Bar => "bar".to_string(),
             Token::Comma => "comma".to_string(),
             Token::Colon => "colon".to_string(),
+            Token::Semicolon => "semicolon".to_string(),
+            Token::Null => "null".to_string(),
+            Token::Boolean(b) => format!("boolean {}", b),
+            Token::Equal => "==".to_string(),
+            Token::NotEqual => "!=".to_string(),
+            Token::Less => "<".to_string(),
+            Token::LessEqual => "<=".to_string(),
+            Token::Greater => ">".to_string(),
+            Token::GreaterEqual => ">=".to_string(),
+            Token::And => "and".to_string(),
+            Token::Or => "or".to_string(),
+            Token::Plus => "+".to_string(),
+            Token::Minus => "-".to_string(),
+            Token::Star => "*".to_string(),
+            Token::Slash => "/".to_string(),
+            Token::Percent => "%".to_string(),
+            Token::DoubleSlash => "//".to_string(),
+            Token::Assign => "=".to_string(),
+            Token::BarAssign => "|=".to_string(),
+            Token::PlusAssign => "+=".to_string(),
+            Token::MinusAssign => "-=".to_string(),
+            Token::StarAssign => "*=".to_string(),
+            Token::SlashAssign => "/=".to_string(),
+            Token::PercentAssign => "%=".to_string(),
+            Token::DoubleSlashAssign => "//=".to_string(),
+            Token::Question => "?".to_string(),
+            Token::Alternatives => "?//".to_string(),
             Token::Number(num) => format!("number {}", num),
             Token::Word(w) => format!("word {}", w),
+            Token::Keyword(w) => format!("keyword {}", w),
+            Token::Field(w) => format!("field .{}", w),
+            Token::Variable(w) => format!("variable ${}", w),
+            Token::Format(w) => format!("format @{}", w),
             Token::Str(s) => format!("string {}", s),
+            Token::Template(_) => "interpolated string".to_string(),
         };
         write!(f, "{}", kind)
     }
@@ -44,6 +139,7 @@ impl<Chars: Iterator<Item = char>> Lexer<Chars> {
         Self {
             source: chars.peekable(),
             next_token: None,
+            pending: None,
         }
     }
 
@@ -63,6 +159,123 @@ impl<Chars: Iterator<Item = char>> Lexer<Chars> {
         self.is_num(c) || self.is_alpha(c)
     }
 
+    /// Consumes the next char if it is the expected one.
+    fn next_is(&mut self, expected: char) -> bool {
+        self.source.next_if_eq(&expected).is_some()
+    }
+
+    fn push_digits(&mut self, word: &mut Vec<char>) {
+        while let Some(&cis) = self.source.peek() {
+            if self.is_num(cis) {
+                word.push(cis);
+                self.source.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn read_word(&mut self, first: char) -> String {
+        let mut word = vec![first];
+        while let Some(&cis) = self.source.peek() {
+            if self.is_alnum(cis) {
+                word.push(cis);
+                self.source.next();
+            } else {
+                break;
+            }
+        }
+        word.iter().collect()
+    }
+
+    /// Reads the rest of a string after its opening quote, handling JSON escapes
+    /// and lexing `\(...)` interpolations into nested token streams.
+    fn read_string(&mut self) -> Result<Option<Token>, Error> {
+        let mut parts = Vec::new();
+        let mut string = String::new();
+        while let Some(c) = self.source.next() {
+            match c {
+                '"' if parts.is_empty() => return Ok(Some(Token::Str(string))),
+                '"' => {
+                    parts.push(StringPart::Literal(string));
+                    return Ok(Some(Token::Template(parts)));
+                }
+                '\\' => match self.source.next() {
+                    Some('(') => {
+                        parts.push(StringPart::Literal(std::mem::take(&mut string)));
+                        parts.push(StringPart::Tokens(self.read_interpolation()?));
+                    }
+                    Some('u') => string.push(self.read_unicode_escape()?),
+                    Some(escaped) => string.push(match escaped {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{08}',
+                        'f' => '\u{0c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        _ => return Err(Error::Lexer(format!("Invalid escape: \\{}", escaped))),
+                    }),
+                    None => break,
+                },
+                _ => string.push(c),
+            }
+        }
+        Err(Error::Lexer("Expected closing quote".to_string()))
+    }
+
+    /// Lexes the tokens of an interpolation up to its closing paren.
+    fn read_interpolation(&mut self) -> Result<Vec<Token>, Error> {
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        loop {
+            match self.get_next()? {
+                Some(Token::CloseParen) if depth == 0 => return Ok(tokens),
+                Some(token) => {
+                    match token {
+                        Token::OpenParen => depth += 1,
+                        Token::CloseParen => depth -= 1,
+                        _ => (),
+                    }
+                    tokens.push(token);
+                }
+                None => {
+                    return Err(Error::Lexer(
+                        "Expected closing paren of string interpolation".to_string(),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn read_hex(&mut self) -> Result<u32, Error> {
+        let hex: String = self.source.by_ref().take(4).collect();
+        match u32::from_str_radix(&hex, 16) {
+            Ok(n) if hex.len() == 4 => Ok(n),
+            _ => Err(Error::Lexer(format!("Invalid unicode escape: \\u{}", hex))),
+        }
+    }
+
+    /// Reads the digits of a `\uXXXX` escape, combining UTF-16 surrogate pairs.
+    fn read_unicode_escape(&mut self) -> Result<char, Error> {
+        let high = self.read_hex()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !(self.next_is('\\') && self.next_is('u')) {
+                return Err(Error::Lexer("Expected low surrogate escape".to_string()));
+            }
+            let low = self.read_hex()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(Error::Lexer(format!("Invalid low surrogate: {:04x}", low)));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code)
+            .ok_or_else(|| Error::Lexer(format!("Invalid unicode escape: {:04x}", code)))
+    }
+
     pub fn next(&mut self) -> Result<Option<Token>, Error> {
         let mut next = self.next_token.clone();
         if next.is_none() {
@@ -85,59 +298,118 @@ impl<Chars: Iterator<Item = char>> Lexer<Chars> {
     }
 
     pub fn get_next(&mut self) -> Result<Option<Token>, Error> {
+        if let Some(token) = self.pending.take() {
+            return Ok(Some(token));
+        }
         match self.source.next() {
             Some(c) => {
                 match c {
-                    '.' => Ok(Some(Token::Dot)),
+                    '.' => match self.source.peek().copied() {
+                        Some('.') => {
+                            self.source.next();
+                            Ok(Some(Token::DoubleDot))
+                        }
+                        Some(first) if self.is_alpha(first) => {
+                            self.source.next();
+                            Ok(Some(Token::Field(self.read_word(first))))
+                        }
+                        _ => Ok(Some(Token::Dot)),
+                    },
+                    '|' if self.next_is('=') => Ok(Some(Token::BarAssign)),
                     '|' => Ok(Some(Token::Bar)),
                     ',' => Ok(Some(Token::Comma)),
                     ':' => Ok(Some(Token::Colon)),
+                    ';' => Ok(Some(Token::Semicolon)),
                     '[' => Ok(Some(Token::OpenBracket)),
                     ']' => Ok(Some(Token::CloseBracket)),
-                    '"' => {
-                        let mut string = String::new();
-                        for c in self.source.by_ref() {
-                            if '"' == c {
-                                return Ok(Some(Token::Str(string)));
-                            }
-                            string.push(c);
+                    '(' => Ok(Some(Token::OpenParen)),
+                    ')' => Ok(Some(Token::CloseParen)),
+                    '{' => Ok(Some(Token::OpenBrace)),
+                    '}' => Ok(Some(Token::CloseBrace)),
+                    '$' => match self.source.peek().copied() {
+                        Some(first) if self.is_alpha(first) => {
+                            self.source.next();
+                            Ok(Some(Token::Variable(self.read_word(first))))
+                        }
+                        _ => Err(Error::Lexer("Expected variable name after $".to_string())),
+                    },
+                    '@' => match self.source.peek().copied() {
+                        Some(first) if self.is_alpha(first) => {
+                            self.source.next();
+                            Ok(Some(Token::Format(self.read_word(first))))
+                        }
+                        _ => Err(Error::Lexer("Expected format name after @".to_string())),
+                    },
+                    '?' if self.next_is('/') => {
+                        if self.next_is('/') {
+                            return Ok(Some(Token::Alternatives));
                         }
-                        Err(Error::Lexer("Expected closing quote".to_string()))
+                        self.pending = Some(Token::Slash);
+                        Ok(Some(Token::Question))
                     }
+                    '?' => Ok(Some(Token::Question)),
+                    '=' if self.next_is('=') => Ok(Some(Token::Equal)),
+                    '=' => Ok(Some(Token::Assign)),
+                    '!' if self.next_is('=') => Ok(Some(Token::NotEqual)),
+                    '<' if self.next_is('=') => Ok(Some(Token::LessEqual)),
+                    '<' => Ok(Some(Token::Less)),
+                    '>' if self.next_is('=') => Ok(Some(Token::GreaterEqual)),
+                    '>' => Ok(Some(Token::Greater)),
+                    '+' if self.next_is('=') => Ok(Some(Token::PlusAssign)),
+                    '+' => Ok(Some(Token::Plus)),
+                    '-' if self.next_is('=') => Ok(Some(Token::MinusAssign)),
+                    '-' => Ok(Some(Token::Minus)),
+                    '*' if self.next_is('=') => Ok(Some(Token::StarAssign)),
+                    '*' => Ok(Some(Token::Star)),
+                    '/' if self.next_is('/') => match self.next_is('=') {
+                        true => Ok(Some(Token::DoubleSlashAssign)),
+                        false => Ok(Some(Token::DoubleSlash)),
+                    },
+                    '/' if self.next_is('=') => Ok(Some(Token::SlashAssign)),
+                    '/' => Ok(Some(Token::Slash)),
+                    '%' if self.next_is('=') => Ok(Some(Token::PercentAssign)),
+                    '%' => Ok(Some(Token::Percent)),
+                    '"' => self.read_string(),
                     _ => {
                         // Number
                         if self.is_num(c) {
                             let mut word = vec![c];
-                            while let Some(&cis) = self.source.peek() {
-                                if self.is_num(cis) {
-                                    word.push(cis);
-                                    self.source.next();
-                                } else {
-                                    break;
+                            self.push_digits(&mut word);
+                            if self.next_is('.') {
+                                word.push('.');
+                                self.push_digits(&mut word);
+                            }
+                            if let Some(e) = self.source.next_if(|&c| c == 'e' || c == 'E') {
+                                word.push(e);
+                                if let Some(sign) = self.source.next_if(|&c| c == '+' || c == '-') {
+                                    word.push(sign);
                                 }
+                                self.push_digits(&mut word);
                             }
                             return Ok(Some(Token::Number(word.iter().collect())));
                         }
 
                         // Word
                         if self.is_alpha(c) {
-                            let mut word = vec![c];
-                            while let Some(&cis) = self.source.peek() {
-                                if self.is_alnum(cis) {
-                                    word.push(cis);
-                                    self.source.next();
-                                } else {
-                                    break;
+                            let word = self.read_word(c);
+                            return match word.as_str() {
+                                "null" => Ok(Some(Token::Null)),
+                                "true" => Ok(Some(Token::Boolean(true))),
+                                "false" => Ok(Some(Token::Boolean(false))),
+                                "and" => Ok(Some(Token::And)),
+                                "or" => Ok(Some(Token::Or)),
+                                _ if KEYWORDS.contains(&word.as_str()) => {
+                                    Ok(Some(Token::Keyword(word)))
                                 }
-                            }
-                            return Ok(Some(Token::Word(word.iter().collect())));
+                                _ => Ok(Some(Token::Word(word))),
+                            };
                         }
 
                         if !c.is_whitespace() {
                             return Err(Error::Lexer(format!("Unexpected char: {}", c)));
                         }
 
-                        self.next()
+                        self.get_next()
                     }
                 }
             }
@@ -155,6 +427,7 @@ impl Lexer<std::vec::IntoIter<char>> {
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod test {
     use super::*;
 
@@ -239,6 +512,274 @@ mod test {
         assert!(r3.is_none(), "expected end of input");
     }
 
+    #[test]
+    fn skips_whitespace_between_symbols() {
+        let mut lex = Lexer::new(". , [ ]");
+        assert_eq!(Some(Token::Dot), lex.next().unwrap(), "expected dot");
+        assert_eq!(Some(Token::Comma), lex.next().unwrap(), "expected comma");
+        assert_eq!(
+            Some(Token::OpenBracket),
+            lex.next().unwrap(),
+            "expected open bracket"
+        );
+        assert_eq!(
+            Some(Token::CloseBracket),
+            lex.next().unwrap(),
+            "expected close bracket"
+        );
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
+    #[test]
+    fn lexes_parens() {
+        let mut lex = Lexer::new("(.)");
+        assert_eq!(
+            Some(Token::OpenParen),
+            lex.next().unwrap(),
+            "expected open paren"
+        );
+        assert_eq!(Some(Token::Dot), lex.next().unwrap(), "expected dot");
+        assert_eq!(
+            Some(Token::CloseParen),
+            lex.next().unwrap(),
+            "expected close paren"
+        );
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
+    #[test]
+    fn lexes_null() {
+        let mut lex = Lexer::new("null nullable");
+        assert_eq!(Some(Token::Null), lex.next().unwrap(), "expected null");
+        assert_eq!(
+            Some(Token::Word("nullable".to_string())),
+            lex.next().unwrap(),
+            "expected word token"
+        );
+    }
+
+    #[test]
+    fn lexes_real_numbers() {
+        let mut lex = Lexer::new("1.5 2e3 7.25E-2");
+        assert_eq!(Some(Token::Number("1.5".to_string())), lex.next().unwrap());
+        assert_eq!(Some(Token::Number("2e3".to_string())), lex.next().unwrap());
+        assert_eq!(
+            Some(Token::Number("7.25E-2".to_string())),
+            lex.next().unwrap()
+        );
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
+    #[test]
+    fn lexes_comparisons() {
+        let mut lex = Lexer::new("== != < <= > >=");
+        assert_eq!(Some(Token::Equal), lex.next().unwrap());
+        assert_eq!(Some(Token::NotEqual), lex.next().unwrap());
+        assert_eq!(Some(Token::Less), lex.next().unwrap());
+        assert_eq!(Some(Token::LessEqual), lex.next().unwrap());
+        assert_eq!(Some(Token::Greater), lex.next().unwrap());
+        assert_eq!(Some(Token::GreaterEqual), lex.next().unwrap());
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
+    #[test]
+    fn lexes_arithmetic() {
+        let mut lex = Lexer::new("1+-2*3/4%5");
+        assert_eq!(Some(Token::Number("1".to_string())), lex.next().unwrap());
+        assert_eq!(Some(Token::Plus), lex.next().unwrap());
+        assert_eq!(Some(Token::Minus), lex.next().unwrap());
+        assert_eq!(Some(Token::Number("2".to_string())), lex.next().unwrap());
+        assert_eq!(Some(Token::Star), lex.next().unwrap());
+        assert_eq!(Some(Token::Number("3".to_string())), lex.next().unwrap());
+        assert_eq!(Some(Token::Slash), lex.next().unwrap());
+        assert_eq!(Some(Token::Number("4".to_string())), lex.next().unwrap());
+        assert_eq!(Some(Token::Percent), lex.next().unwrap());
+        assert_eq!(Some(Token::Number("5".to_string())), lex.next().unwrap());
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
+    #[test]
+    fn lexes_boolean_words() {
+        let mut lex = Lexer::new("true and false or not");
+        assert_eq!(Some(Token::Boolean(true)), lex.next().unwrap());
+        assert_eq!(Some(Token::And), lex.next().unwrap());
+        assert_eq!(Some(Token::Boolean(false)), lex.next().unwrap());
+        assert_eq!(Some(Token::Or), lex.next().unwrap());
+        assert_eq!(Some(Token::Word("not".to_string())), lex.next().unwrap());
+    }
+
+    #[test]
+    fn lexes_keywords() {
+        let mut lex = Lexer::new("if then elif else end endless");
+        for word in ["if", "then", "elif", "else", "end"] {
+            assert_eq!(Some(Token::Keyword(word.to_string())), lex.next().unwrap());
+        }
+        assert_eq!(
+            Some(Token::Word("endless".to_string())),
+            lex.next().unwrap()
+        );
+    }
+
+    #[test]
+    fn lexes_fields() {
+        let mut lex = Lexer::new(".end . end");
+        assert_eq!(Some(Token::Field("end".to_string())), lex.next().unwrap());
+        assert_eq!(Some(Token::Dot), lex.next().unwrap());
+        assert_eq!(Some(Token::Keyword("end".to_string())), lex.next().unwrap());
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
+    #[test]
+    fn lexes_variables() {
+        let mut lex = Lexer::new(". as [$a, {$b}] ?// $a_1");
+        let expected = vec![
+            Token::Dot,
+            Token::Keyword("as".to_string()),
+            Token::OpenBracket,
+            Token::Variable("a".to_string()),
+            Token::Comma,
+            Token::OpenBrace,
+            Token::Variable("b".to_string()),
+            Token::CloseBrace,
+            Token::CloseBracket,
+            Token::Alternatives,
+            Token::Variable("a_1".to_string()),
+        ];
+        for token in expected {
+            assert_eq!(Some(token), lex.next().unwrap());
+        }
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+        assert!(Lexer::new("$ a").next().is_err(), "expected variable name");
+    }
+
+    #[test]
+    fn lexes_object_construction() {
+        let mut lex = Lexer::new("{$a, (.b): @base64}");
+        let expected = vec![
+            Token::OpenBrace,
+            Token::Variable("a".to_string()),
+            Token::Comma,
+            Token::OpenParen,
+            Token::Field("b".to_string()),
+            Token::CloseParen,
+            Token::Colon,
+            Token::Format("base64".to_string()),
+            Token::CloseBrace,
+        ];
+        for token in expected {
+            assert_eq!(Some(token), lex.next().unwrap());
+        }
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
+    #[test]
+    fn lexes_string_escapes() {
+        let mut lex = Lexer::new(r#""a \"quoted\" key\n\t\\ \/ \u00e9 \ud83d\ude00""#);
+        assert_eq!(
+            Some(Token::Str("a \"quoted\" key\n\t\\ / é 😀".to_string())),
+            lex.next().unwrap()
+        );
+        assert!(Lexer::new(r#""\x""#).next().is_err(), "invalid escape");
+        assert!(
+            Lexer::new(r#""\u00""#).next().is_err(),
+            "short unicode escape"
+        );
+        assert!(Lexer::new(r#""\ud83d""#).next().is_err(), "lone surrogate");
+    }
+
+    #[test]
+    fn lexes_string_interpolation() {
+        let mut lex = Lexer::new(r#""Hi \(.name + "!\(1)"), \((.age))""#);
+        let expected = Token::Template(vec![
+            StringPart::Literal("Hi ".to_string()),
+            StringPart::Tokens(vec![
+                Token::Field("name".to_string()),
+                Token::Plus,
+                Token::Template(vec![
+                    StringPart::Literal("!".to_string()),
+                    StringPart::Tokens(vec![Token::Number("1".to_string())]),
+                    StringPart::Literal("".to_string()),
+                ]),
+            ]),
+            StringPart::Literal(", ".to_string()),
+            StringPart::Tokens(vec![
+                Token::OpenParen,
+                Token::Field("age".to_string()),
+                Token::CloseParen,
+            ]),
+            StringPart::Literal("".to_string()),
+        ]);
+        assert_eq!(Some(expected), lex.next().unwrap());
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+        assert!(
+            Lexer::new(r#""\(.a""#).next().is_err(),
+            "unclosed interpolation"
+        );
+    }
+
+    #[test]
+    fn lexes_question_marks() {
+        let mut lex = Lexer::new(".a? ?/2 ?//");
+        let expected = vec![
+            Token::Field("a".to_string()),
+            Token::Question,
+            Token::Question,
+            Token::Slash,
+            Token::Number("2".to_string()),
+            Token::Alternatives,
+        ];
+        for token in expected {
+            assert_eq!(Some(token), lex.next().unwrap());
+        }
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
+    #[test]
+    fn lexes_double_slashes() {
+        let mut lex = Lexer::new(".a // 1 / 2");
+        let expected = vec![
+            Token::Field("a".to_string()),
+            Token::DoubleSlash,
+            Token::Number("1".to_string()),
+            Token::Slash,
+            Token::Number("2".to_string()),
+        ];
+        for token in expected {
+            assert_eq!(Some(token), lex.next().unwrap());
+        }
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
+    #[test]
+    fn lexes_double_dots() {
+        let mut lex = Lexer::new("..|.a");
+        assert_eq!(Some(Token::DoubleDot), lex.next().unwrap());
+        assert_eq!(Some(Token::Bar), lex.next().unwrap());
+        assert_eq!(Some(Token::Field("a".to_string())), lex.next().unwrap());
+    }
+
+    #[test]
+    fn lexes_assignments() {
+        let mut lex = Lexer::new("= == |= | += -= *= /= %= //= //");
+        let expected = vec![
+            Token::Assign,
+            Token::Equal,
+            Token::BarAssign,
+            Token::Bar,
+            Token::PlusAssign,
+            Token::MinusAssign,
+            Token::StarAssign,
+            Token::SlashAssign,
+            Token::PercentAssign,
+            Token::DoubleSlashAssign,
+            Token::DoubleSlash,
+        ];
+        for token in expected {
+            assert_eq!(Some(token), lex.next().unwrap());
+        }
+        assert!(lex.next().unwrap().is_none(), "expected end of input");
+    }
+
     #[test]
     fn lexes_ranges() {
         let mut lex = Lexer::new("[161:1312]");
@@ -0,0 +1,6 @@
+pub mod dataset;
+pub mod error;
+pub mod filter;
+mod lexer;
+pub mod output;
+pub mod parser;
@@ -1,23 +1,162 @@
-mod error;
-use error::*;
+use rq::dataset::*;
+use rq::error::*;
+use rq::filter::*;
+use rq::output::*;
+use rq::parser::*;
+use std::io::ErrorKind;
 
-mod filter;
-use filter::*;
+const USAGE: &str = "Usage: rq [OPTIONS] <FILTER> [FILES...]
+       rq --repl [OPTIONS] <FILE>
 
-mod lexer;
+Applies FILTER to each of the FILES, or to standard input if none are given.
 
-mod parser;
-use parser::*;
+Options:
+  -n, --null-input        use null as the single input instead of reading data
+  -c, --compact           print JSON on a single line
+      --indent <N>        indent JSON or YAML output by N spaces (default: 2)
+  -y, --yaml              print results as a YAML document stream
+      --yaml-flow         use flow style collections in YAML output
+      --yaml-quote <Q>    YAML string quoting: minimal, single or double
+      --repl              start an interactive prompt for the given file
+  -h, --help              print this help";
 
-mod dataset;
-use dataset::*;
+const EXIT_USAGE: i32 = 2;
+const EXIT_COMPILE: i32 = 3;
+const EXIT_RUNTIME: i32 = 5;
 
-fn get_input(prompt: &str) -> Result<String, Error> {
+enum OutputFormat {
+    Json(JsonStyle),
+    Yaml(YamlStyle),
+}
+
+struct Options {
+    help: bool,
+    filter: Option<String>,
+    files: Vec<String>,
+    repl: bool,
+    null_input: bool,
+    output: OutputFormat,
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut help = false;
+    let mut positional = Vec::new();
+    let mut repl = false;
+    let mut null_input = false;
+    let mut compact = false;
+    let mut yaml = false;
+    let mut yaml_style: YamlStyle = Default::default();
+    let mut indent = 2;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => help = true,
+            "--repl" => repl = true,
+            "-n" | "--null-input" => null_input = true,
+            "-c" | "--compact" => compact = true,
+            "-y" | "--yaml" => yaml = true,
+            "--yaml-flow" => {
+                yaml = true;
+                yaml_style.collections = YamlCollections::Flow;
+            }
+            "--yaml-quote" => {
+                yaml = true;
+                yaml_style.quoting = match args.next().as_deref() {
+                    Some("minimal") => YamlQuoting::Minimal,
+                    Some("single") => YamlQuoting::Single,
+                    Some("double") => YamlQuoting::Double,
+                    _ => return Err("--yaml-quote expects minimal, single or double".to_string()),
+                };
+            }
+            "--indent" => {
+                indent = match args.next().map(|n| n.parse::<usize>()) {
+                    Some(Ok(n)) => n,
+                    _ => return Err("--indent expects a number".to_string()),
+                };
+            }
+            "-" => positional.push(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    // The first positional argument is the filter unless in REPL mode, where
+    // they are all files; `-` always stands for standard input.
+    let filter = match positional.iter().position(|arg| arg != "-") {
+        Some(i) if !repl => Some(positional.remove(i)),
+        _ => None,
+    };
+    let files = positional;
+
+    if !help && !repl && filter.is_none() {
+        return Err("missing filter".to_string());
+    }
+    if !help && repl && files.len() != 1 {
+        return Err("--repl expects exactly one file".to_string());
+    }
+
+    yaml_style.indent = indent;
+    let output = if yaml {
+        OutputFormat::Yaml(yaml_style)
+    } else if compact {
+        OutputFormat::Json(JsonStyle::Compact)
+    } else {
+        OutputFormat::Json(JsonStyle::Pretty(indent))
+    };
+
+    Ok(Options {
+        help,
+        filter,
+        files,
+        repl,
+        null_input,
+        output,
+    })
+}
+
+fn load_input(filename: &str) -> Result<Vec<Data>, Error> {
+    if filename == "-" {
+        use std::io::Read;
+        let mut contents = String::new();
+        std::io::stdin().read_to_string(&mut contents)?;
+        load_str_stream(&contents)
+    } else {
+        Ok(vec![load_file(filename)?])
+    }
+}
+
+struct Printer {
+    format: OutputFormat,
+    printed: bool,
+}
+
+impl Printer {
+    fn print(&mut self, data: &Data) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut out = std::io::stdout().lock();
+        match &self.format {
+            OutputFormat::Json(style) => writeln!(out, "{}", to_json(data, *style))?,
+            OutputFormat::Yaml(style) => {
+                if self.printed {
+                    writeln!(out, "---")?;
+                }
+                writeln!(out, "{}", to_yaml(data, style))?;
+            }
+        }
+        self.printed = true;
+        Ok(())
+    }
+}
+
+fn get_input(prompt: &str) -> Result<Option<String>, Error> {
     use std::io::{stdin, stdout, Write};
     let mut s = String::new();
     print!("{} ", prompt);
     stdout().flush()?;
-    stdin().read_line(&mut s)?;
+    if stdin().read_line(&mut s)? == 0 {
+        return Ok(None);
+    }
 
     if let Some('\n') = s.chars().next_back() {
         s.pop();
@@ -26,41 +165,129 @@ fn get_input(prompt: &str) -> Result<String, Error> {
         s.pop();
     }
 
-    Ok(s)
+    Ok(Some(s))
 }
 
-fn report_error<T>(result: Result<T, Error>) {
-    match result.err().unwrap() {
-        Error::Lexer(err) => eprintln!("[Lexer] ERROR: {}", err),
-        Error::Parser(err) => eprintln!("[Parser] ERROR: {}", err),
-        Error::Filter => eprintln!("Expression did not match anything"),
-        _ => eprintln!("Generic error"),
+fn repl(data: Data, printer: &mut Printer) -> Result<(), Error> {
+    while let Some(input) = get_input(">")? {
+        if input == "q" || input == "quit" {
+            break;
+        }
+        let mut parser = ExpressionParser::new(&input);
+        let filters = match parser.parse() {
+            Ok(filters) => filters,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        println!("{}", filters);
+
+        for result in filters.apply(data.clone()) {
+            match result {
+                Ok(result) => match printer.print(&result) {
+                    Err(err) if err.kind() == ErrorKind::BrokenPipe => return Ok(()),
+                    other => other?,
+                },
+                Err(err) => {
+                    eprintln!("{}", err);
+                    break;
+                }
+            }
+        }
     }
+    Ok(())
 }
 
-fn main() -> Result<(), Error> {
-    let filename = "test-data/one.json";
-    let data = load_file(filename)?;
+fn run(options: Options) -> i32 {
+    let mut printer = Printer {
+        format: options.output,
+        printed: false,
+    };
 
-    loop {
-        let input = get_input(">")?;
-        if input == "q" || input == "quit" {
-            break;
+    if options.repl {
+        let result = load_input(&options.files[0]).and_then(|mut values| match values.len() {
+            1 => repl(values.remove(0), &mut printer),
+            n => Err(Error::Dataset(format!(
+                "--repl expects a single value, got {}",
+                n
+            ))),
+        });
+        return match result {
+            Ok(()) => 0,
+            Err(err) => {
+                eprintln!("{}", err);
+                EXIT_USAGE
+            }
+        };
+    }
+
+    let source = options.filter.unwrap_or_default();
+    let filters = match ExpressionParser::new(&source).parse() {
+        Ok(filters) => filters,
+        Err(err) => {
+            eprintln!("{}", err);
+            return EXIT_COMPILE;
         }
-        let mut parser = ExpressionParser::new(&input);
-        let filters = parser.parse();
-        if filters.is_err() {
-            report_error(filters);
-            continue;
+    };
+
+    let mut inputs = options.files;
+    if inputs.is_empty() {
+        inputs.push("-".to_string());
+    }
+
+    let mut status = 0;
+    for input in inputs {
+        let values = if options.null_input {
+            vec![Data::Null]
+        } else {
+            match load_input(&input) {
+                Ok(values) => values,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    status = EXIT_USAGE;
+                    continue;
+                }
+            }
+        };
+        for data in values {
+            for result in filters.apply(data) {
+                match result {
+                    // Stop quietly once whoever reads our output has gone away.
+                    Ok(result) => match printer.print(&result) {
+                        Ok(()) => {}
+                        Err(err) if err.kind() == ErrorKind::BrokenPipe => return status,
+                        Err(err) => {
+                            eprintln!("rq: {}", err);
+                            return EXIT_RUNTIME;
+                        }
+                    },
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        status = EXIT_RUNTIME;
+                        break;
+                    }
+                }
+            }
         }
-        println!("{:?}", filters);
+        if options.null_input {
+            break;
+        }
+    }
+    status
+}
 
-        let result = filters.unwrap().apply(data.clone());
-        if result.is_err() {
-            report_error(result);
-            continue;
+fn main() {
+    let options = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("rq: {}\n\n{}", err, USAGE);
+            std::process::exit(EXIT_USAGE);
         }
-        println!("{:?}", result.unwrap());
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return;
     }
-    Ok(())
+    std::process::exit(run(options));
 }
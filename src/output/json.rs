@@ -0,0 +1,144 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JsonStyle {
+    Compact,
+    Pretty(usize),
+}
+
+/// Serializes data as JSON text.
+pub fn to_json(data: &Data, style: JsonStyle) -> String {
+    let mut out = String::new();
+    write_json(&mut out, data, style, 0);
+    out
+}
+
+fn write_json(out: &mut String, data: &Data, style: JsonStyle, depth: usize) {
+    match data {
+        Data::Null => out.push_str("null"),
+        Data::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Data::Integer(n) => out.push_str(&n.to_string()),
+        Data::Real(n) => out.push_str(&n.to_string()),
+        Data::String(s) => write_json_string(out, s),
+        Data::Array(arr) => {
+            if arr.is_empty() {
+                out.push_str("[]");
+                return;
+            }
+            out.push('[');
+            for (idx, item) in arr.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_newline(out, style, depth + 1);
+                write_json(out, item, style, depth + 1);
+            }
+            write_newline(out, style, depth);
+            out.push(']');
+        }
+        Data::Hash(map) => {
+            if map.is_empty() {
+                out.push_str("{}");
+                return;
+            }
+            out.push('{');
+            for (idx, (key, value)) in map.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_newline(out, style, depth + 1);
+                write_json_string(out, key);
+                out.push(':');
+                if let JsonStyle::Pretty(_) = style {
+                    out.push(' ');
+                }
+                write_json(out, value, style, depth + 1);
+            }
+            write_newline(out, style, depth);
+            out.push('}');
+        }
+    }
+}
+
+fn write_newline(out: &mut String, style: JsonStyle, depth: usize) {
+    if let JsonStyle::Pretty(indent) = style {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent * depth));
+    }
+}
+
+/// Writes a quoted JSON string, escaping quotes, backslashes and control characters.
+pub fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                out.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn sample() -> Data {
+        let mut map = IndexMap::new();
+        map.insert(
+            "name".to_string(),
+            Data::String("Evangelina Fox".to_string()),
+        );
+        map.insert(
+            "tags".to_string(),
+            Data::Array(vec![Data::Integer(1), Data::Null, Data::Boolean(true)]),
+        );
+        map.insert("empty".to_string(), Data::Array(Vec::new()));
+        Data::Hash(map)
+    }
+
+    #[test]
+    fn serializes_compact() {
+        assert_eq!(
+            to_json(&sample(), JsonStyle::Compact),
+            "{\"name\":\"Evangelina Fox\",\"tags\":[1,null,true],\"empty\":[]}"
+        );
+    }
+
+    #[test]
+    fn serializes_pretty() {
+        assert_eq!(
+            to_json(&sample(), JsonStyle::Pretty(2)),
+            "{\n  \"name\": \"Evangelina Fox\",\n  \"tags\": [\n    1,\n    null,\n    true\n  ],\n  \"empty\": []\n}"
+        );
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let data = Data::String("a \"quoted\"\\\n\t\u{1}é".to_string());
+        assert_eq!(
+            to_json(&data, JsonStyle::Compact),
+            "\"a \\\"quoted\\\"\\\\\\n\\t\\u0001é\""
+        );
+    }
+
+    #[test]
+    fn serializes_reals() {
+        let data = Data::Array(vec![
+            Data::Real(Real::with_literal(58.761557, "58.761557")),
+            Data::Real(Real::new(0.5)),
+            Data::Real(Real::new(f64::NAN)),
+        ]);
+        assert_eq!(to_json(&data, JsonStyle::Compact), "[58.761557,0.5,null]");
+    }
+}
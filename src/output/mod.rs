@@ -0,0 +1,7 @@
+use crate::dataset::*;
+
+mod json;
+pub use json::*;
+
+mod yaml;
+pub use yaml::*;
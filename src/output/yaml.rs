@@ -0,0 +1,305 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YamlCollections {
+    Block,
+    Flow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YamlQuoting {
+    /// Quote strings only when they would otherwise be read back as something else.
+    Minimal,
+    Single,
+    Double,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct YamlStyle {
+    pub collections: YamlCollections,
+    pub quoting: YamlQuoting,
+    pub indent: usize,
+}
+
+impl Default for YamlStyle {
+    fn default() -> Self {
+        Self {
+            collections: YamlCollections::Block,
+            quoting: YamlQuoting::Minimal,
+            indent: 2,
+        }
+    }
+}
+
+/// Serializes data as a single YAML document.
+pub fn to_yaml(data: &Data, style: &YamlStyle) -> String {
+    let mut out = String::new();
+    match style.collections {
+        YamlCollections::Block => write_block(&mut out, data, style, 0),
+        YamlCollections::Flow => write_flow(&mut out, data, style),
+    }
+    out
+}
+
+fn is_nested(data: &Data) -> bool {
+    match data {
+        Data::Array(arr) => !arr.is_empty(),
+        Data::Hash(map) => !map.is_empty(),
+        _ => false,
+    }
+}
+
+fn write_block(out: &mut String, data: &Data, style: &YamlStyle, depth: usize) {
+    let indent = style.indent.max(1);
+    match data {
+        Data::Array(arr) if !arr.is_empty() => {
+            let dash = format!("{:<width$}", "-", width = indent.max(2));
+            for (idx, item) in arr.iter().enumerate() {
+                if idx > 0 {
+                    write_indent(out, indent * depth);
+                }
+                out.push_str(&dash);
+                if is_nested(item) {
+                    // Continuation lines of the item are aligned past the dash.
+                    let mut nested = String::new();
+                    write_block(&mut nested, item, style, 0);
+                    let pad = " ".repeat(indent * depth + dash.len());
+                    out.push_str(&nested.replace('\n', &format!("\n{}", pad)));
+                } else {
+                    write_block(out, item, style, depth);
+                }
+                if idx < arr.len() - 1 {
+                    out.push('\n');
+                }
+            }
+        }
+        Data::Hash(map) if !map.is_empty() => {
+            for (idx, (key, value)) in map.iter().enumerate() {
+                if idx > 0 {
+                    write_indent(out, indent * depth);
+                }
+                write_string(out, key, style, false);
+                out.push(':');
+                if is_nested(value) {
+                    out.push('\n');
+                    write_indent(out, indent * (depth + 1));
+                    write_block(out, value, style, depth + 1);
+                } else {
+                    out.push(' ');
+                    write_block(out, value, style, depth + 1);
+                }
+                if idx < map.len() - 1 {
+                    out.push('\n');
+                }
+            }
+        }
+        _ => write_flow(out, data, style),
+    }
+}
+
+fn write_flow(out: &mut String, data: &Data, style: &YamlStyle) {
+    match data {
+        Data::Null => out.push_str("null"),
+        Data::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Data::Integer(n) => out.push_str(&n.to_string()),
+        Data::Real(n) => {
+            let value = n.value();
+            if value.is_nan() {
+                out.push_str(".nan");
+            } else if value.is_infinite() {
+                out.push_str(if value > 0.0 { ".inf" } else { "-.inf" });
+            } else {
+                out.push_str(&n.to_string());
+            }
+        }
+        Data::String(s) => write_string(out, s, style, style.collections == YamlCollections::Flow),
+        Data::Array(arr) => {
+            out.push('[');
+            for (idx, item) in arr.iter().enumerate() {
+                if idx > 0 {
+                    out.push_str(", ");
+                }
+                write_flow(out, item, style);
+            }
+            out.push(']');
+        }
+        Data::Hash(map) => {
+            out.push('{');
+            for (idx, (key, value)) in map.iter().enumerate() {
+                if idx > 0 {
+                    out.push_str(", ");
+                }
+                write_string(out, key, style, true);
+                out.push_str(": ");
+                write_flow(out, value, style);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_indent(out: &mut String, width: usize) {
+    out.push_str(&" ".repeat(width));
+}
+
+fn write_string(out: &mut String, s: &str, style: &YamlStyle, in_flow: bool) {
+    let has_control = s.chars().any(|c| c.is_control());
+    match style.quoting {
+        YamlQuoting::Minimal if !needs_quotes(s, in_flow) => out.push_str(s),
+        YamlQuoting::Minimal | YamlQuoting::Single if !has_control => {
+            out.push('\'');
+            out.push_str(&s.replace('\'', "''"));
+            out.push('\'');
+        }
+        _ => write_json_string(out, s),
+    }
+}
+
+/// Tells whether a plain scalar would be misread, e.g. as a number, boolean or structure.
+fn needs_quotes(s: &str, in_flow: bool) -> bool {
+    const RESERVED: [&str; 16] = [
+        "~", "null", "true", "false", "yes", "no", "on", "off", "y", "n", ".inf", "-.inf", "+.inf",
+        ".nan", "<<", "=",
+    ];
+    if s.is_empty() || RESERVED.contains(&s.to_lowercase().as_str()) {
+        return true;
+    }
+    if s.parse::<f64>().is_ok() || s.starts_with("0x") || s.starts_with("0o") {
+        return true;
+    }
+    if s.starts_with(|c: char| ",[]{}#&*!|>'\"%@`".contains(c) || c.is_whitespace()) {
+        return true;
+    }
+    if s.starts_with(['-', '?', ':']) && (s.len() == 1 || s[1..].starts_with(' ')) {
+        return true;
+    }
+    if s.ends_with(|c: char| c.is_whitespace() || c == ':') {
+        return true;
+    }
+    if s.contains(": ") || s.contains(" #") || s.chars().any(|c| c.is_control()) {
+        return true;
+    }
+    in_flow && s.contains(|c: char| ",[]{}".contains(c))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn sample() -> Data {
+        let mut friend = IndexMap::new();
+        friend.insert("id".to_string(), Data::Integer(0));
+        friend.insert(
+            "name".to_string(),
+            Data::String("Baxter Turner".to_string()),
+        );
+
+        let mut map = IndexMap::new();
+        map.insert(
+            "name".to_string(),
+            Data::String("Evangelina Fox".to_string()),
+        );
+        map.insert(
+            "phone".to_string(),
+            Data::String("+1 (918) 422-4000".to_string()),
+        );
+        map.insert("index".to_string(), Data::String("0".to_string()));
+        map.insert("isActive".to_string(), Data::Boolean(false));
+        map.insert(
+            "tags".to_string(),
+            Data::Array(vec![
+                Data::String("anim".to_string()),
+                Data::String("enim".to_string()),
+            ]),
+        );
+        map.insert("friends".to_string(), Data::Array(vec![Data::Hash(friend)]));
+        map.insert("empty".to_string(), Data::Hash(IndexMap::new()));
+        Data::Hash(map)
+    }
+
+    #[test]
+    fn emits_block_collections() {
+        let yaml = to_yaml(&sample(), &Default::default());
+        assert_eq!(
+            yaml,
+            "name: Evangelina Fox\n\
+             phone: +1 (918) 422-4000\n\
+             index: '0'\n\
+             isActive: false\n\
+             tags:\n  - anim\n  - enim\n\
+             friends:\n  - id: 0\n    name: Baxter Turner\n\
+             empty: {}"
+        );
+    }
+
+    #[test]
+    fn emits_flow_collections() {
+        let style = YamlStyle {
+            collections: YamlCollections::Flow,
+            ..Default::default()
+        };
+        let yaml = to_yaml(&sample(), &style);
+        assert_eq!(
+            yaml,
+            "{name: Evangelina Fox, phone: +1 (918) 422-4000, index: '0', isActive: false, \
+             tags: [anim, enim], friends: [{id: 0, name: Baxter Turner}], empty: {}}"
+        );
+    }
+
+    #[test]
+    fn honors_indent_width() {
+        let style = YamlStyle {
+            indent: 4,
+            ..Default::default()
+        };
+        let data = Data::Array(vec![sample()]);
+        let yaml = to_yaml(&data, &style);
+        assert!(
+            yaml.starts_with("-   name: Evangelina Fox\n    phone:"),
+            "expected padded dash, got {}",
+            yaml
+        );
+        assert!(
+            yaml.contains("    tags:\n        -   anim\n"),
+            "expected nested sequence, got {}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn quotes_strings_by_policy() {
+        let data = Data::Array(vec![
+            Data::String("plain".to_string()),
+            Data::String("it's".to_string()),
+            Data::String("line\nbreak".to_string()),
+        ]);
+        let single = YamlStyle {
+            collections: YamlCollections::Flow,
+            quoting: YamlQuoting::Single,
+            indent: 2,
+        };
+        assert_eq!(
+            to_yaml(&data, &single),
+            "['plain', 'it''s', \"line\\nbreak\"]"
+        );
+        let double = YamlStyle {
+            quoting: YamlQuoting::Double,
+            ..single
+        };
+        assert_eq!(
+            to_yaml(&data, &double),
+            "[\"plain\", \"it's\", \"line\\nbreak\"]"
+        );
+    }
+
+    #[test]
+    fn emitted_yaml_loads_back() {
+        let yaml = to_yaml(&sample(), &Default::default());
+        let loaded = yaml_rust::YamlLoader::load_from_str(&yaml).unwrap();
+        let doc = &loaded[0];
+        assert_eq!(doc["phone"].as_str(), Some("+1 (918) 422-4000"));
+        assert_eq!(doc["index"].as_str(), Some("0"));
+        assert_eq!(doc["friends"][0]["name"].as_str(), Some("Baxter Turner"));
+    }
+}
@@ -1,158 +1,764 @@
+use crate::dataset::*;
 use crate::error::*;
 use crate::filter::*;
 use crate::lexer::*;
+use std::rc::Rc;
+
+/// Where the parser reads tokens from: the source text, or the tokens
+/// the lexer collected for a string interpolation.
+enum TokenSource {
+    Lexer(Lexer<std::vec::IntoIter<char>>),
+    Tokens(std::vec::IntoIter<Token>),
+}
+
+impl TokenSource {
+    fn next(&mut self) -> Result<Option<Token>, Error> {
+        match self {
+            TokenSource::Lexer(lex) => lex.next(),
+            TokenSource::Tokens(tokens) => Ok(tokens.next()),
+        }
+    }
+}
 
 pub struct ExpressionParser {
-    lex: Lexer<std::vec::IntoIter<char>>,
+    source: TokenSource,
     token: Option<Token>,
+    /// Variables in scope at the current position, innermost last.
+    variables: Vec<String>,
+    /// User-defined functions and parameters in scope, by name and arity.
+    functions: Vec<(String, usize)>,
 }
 
 impl ExpressionParser {
     pub fn new(source: &str) -> Self {
-        let lex = Lexer::new(source);
-        Self { lex, token: None }
+        Self {
+            source: TokenSource::Lexer(Lexer::new(source)),
+            token: None,
+            variables: Vec::new(),
+            functions: Vec::new(),
+        }
     }
 
     pub fn parse(&mut self) -> Result<FilterExpression, Error> {
-        let mut filter: FilterExpression = Default::default();
+        self.next()?;
+        if self.token.is_none() {
+            return Ok(FilterExpression::Filter(FilterType::Current));
+        }
+        let filter = self.parse_expression(0)?;
+        if let Some(token) = &self.token {
+            return Err(Error::Parser(format!("unexpected token: {}", token)));
+        }
+        Ok(filter)
+    }
+
+    /// Precedence climbing over binary operators: parses operands and every operator
+    /// binding at least as tightly as `min_precedence`.
+    fn parse_expression(&mut self, min_precedence: u8) -> Result<FilterExpression, Error> {
+        let mut lhs = self.parse_term()?;
+        while let Some(op) = self.operator() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.next()?;
+            let next_precedence = if op.is_right_associative() {
+                op.precedence()
+            } else {
+                op.precedence() + 1
+            };
+            let rhs = self.parse_expression(next_precedence)?;
+            lhs = FilterExpression::binary(op, lhs, rhs);
+            if let Some(next) = self.operator() {
+                if op.is_non_associative() && next.precedence() == op.precedence() {
+                    return Err(Error::Parser(format!(
+                        "operators {} and {} can not be chained without parentheses",
+                        op, next
+                    )));
+                }
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn operator(&self) -> Option<Operator> {
+        match &self.token {
+            Some(Token::Bar) => Some(Operator::Pipe),
+            Some(Token::Comma) => Some(Operator::Comma),
+            Some(Token::DoubleSlash) => Some(Operator::Alternative),
+            Some(Token::Assign) => Some(Operator::Assign),
+            Some(Token::BarAssign) => Some(Operator::Update),
+            Some(Token::PlusAssign) => Some(Operator::AddAssign),
+            Some(Token::MinusAssign) => Some(Operator::SubtractAssign),
+            Some(Token::StarAssign) => Some(Operator::MultiplyAssign),
+            Some(Token::SlashAssign) => Some(Operator::DivideAssign),
+            Some(Token::PercentAssign) => Some(Operator::ModuloAssign),
+            Some(Token::DoubleSlashAssign) => Some(Operator::AlternativeAssign),
+            Some(Token::Or) => Some(Operator::Or),
+            Some(Token::And) => Some(Operator::And),
+            Some(Token::Equal) => Some(Operator::Equal),
+            Some(Token::NotEqual) => Some(Operator::NotEqual),
+            Some(Token::Less) => Some(Operator::Less),
+            Some(Token::LessEqual) => Some(Operator::LessEqual),
+            Some(Token::Greater) => Some(Operator::Greater),
+            Some(Token::GreaterEqual) => Some(Operator::GreaterEqual),
+            Some(Token::Plus) => Some(Operator::Add),
+            Some(Token::Minus) => Some(Operator::Subtract),
+            Some(Token::Star) => Some(Operator::Multiply),
+            Some(Token::Slash) => Some(Operator::Divide),
+            Some(Token::Percent) => Some(Operator::Modulo),
+            _ => None,
+        }
+    }
 
+    fn parse_number(num: &str) -> Result<Data, Error> {
+        if let Ok(n) = num.parse::<i64>() {
+            return Ok(Data::Integer(n));
+        }
+        match num.parse::<f64>() {
+            Ok(n) => Ok(Data::Real(Real::with_literal(n, num))),
+            Err(_) => Err(Error::Parser(format!("invalid number: {}", num))),
+        }
+    }
+
+    /// Parses a primary expression along with any filters juxtaposed to it, as in `.[0]keys`.
+    /// A term followed by `as` binds variables for the rest of the expression.
+    fn parse_term(&mut self) -> Result<FilterExpression, Error> {
+        let term = self.parse_juxtaposed()?;
+        if self.token == Some(Token::Keyword("as".to_string())) {
+            return self.parse_binding(term);
+        }
+        Ok(term)
+    }
+
+    fn parse_juxtaposed(&mut self) -> Result<FilterExpression, Error> {
+        let mut term = self.parse_primary()?;
+        while let Some(
+            Token::Dot | Token::Field(_) | Token::OpenBracket | Token::Word(_) | Token::Question,
+        ) = &self.token
+        {
+            if self.token == Some(Token::OpenBracket) {
+                term = self.parse_index(term)?;
+                continue;
+            }
+            if self.token == Some(Token::Question) {
+                self.next()?;
+                term = FilterExpression::Try(Rc::new(term), None);
+                continue;
+            }
+            let next = self.parse_primary()?;
+            term = FilterExpression::chain(term, next);
+        }
+        Ok(term)
+    }
+
+    /// Parses a bracketed suffix of `target`: `[]`, `[key]` or `[from:to]`.
+    /// Literal keys and bounds become plain filters, anything else is computed.
+    fn parse_index(&mut self, target: FilterExpression) -> Result<FilterExpression, Error> {
+        self.expect(Token::OpenBracket)?;
+        let from = match self.token {
+            Some(Token::Colon) | Some(Token::CloseBracket) => None,
+            _ => Some(self.parse_expression(0)?),
+        };
+        let mut to = None;
+        let is_slice = self.token == Some(Token::Colon);
+        if is_slice {
+            self.next()?;
+            if self.token != Some(Token::CloseBracket) {
+                to = Some(self.parse_expression(0)?);
+            }
+        }
+        self.expect(Token::CloseBracket)?;
+
+        let filter = match (&from, is_slice) {
+            (None, false) => Some(FilterType::Array),
+            (Some(FilterExpression::Literal(Data::String(name))), false) => {
+                Some(FilterType::Entry(name.to_string()))
+            }
+            (Some(FilterExpression::Literal(Data::Integer(idx))), false) if *idx >= 0 => {
+                Some(FilterType::Member(*idx as usize))
+            }
+            (_, true) => match (literal_bound(&from), literal_bound(&to)) {
+                (Some(start), Some(end)) if to.is_none() || end > 0 => {
+                    Some(ExpressionParser::new_range(start, end)?)
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        Ok(match filter {
+            Some(filter) if matches!(target, FilterExpression::Filter(FilterType::Current)) => {
+                FilterExpression::Filter(filter)
+            }
+            Some(filter) => FilterExpression::chain(target, FilterExpression::Filter(filter)),
+            None if is_slice => {
+                FilterExpression::Slice(Rc::new(target), from.map(Rc::new), to.map(Rc::new))
+            }
+            None => FilterExpression::Index(Rc::new(target), Rc::new(from.unwrap())),
+        })
+    }
+
+    /// Parses `as` and the destructuring alternatives following it.
+    fn parse_patterns(&mut self) -> Result<Vec<Pattern>, Error> {
+        self.expect(Token::Keyword("as".to_string()))?;
+        let mut patterns = vec![self.parse_pattern()?];
+        while self.token == Some(Token::Alternatives) {
+            self.next()?;
+            patterns.push(self.parse_pattern()?);
+        }
+        Ok(patterns)
+    }
+
+    /// Parses the patterns and body of `term as $x | body`.
+    fn parse_binding(&mut self, source: FilterExpression) -> Result<FilterExpression, Error> {
+        let patterns = self.parse_patterns()?;
+        self.expect(Token::Bar)?;
+        let scope = self.variables.len();
+        self.variables
+            .extend(patterns.iter().flat_map(Pattern::variables));
+        let body = self.parse_expression(0);
+        self.variables.truncate(scope);
+        Ok(FilterExpression::Bind(
+            Rc::new(source),
+            patterns,
+            Rc::new(body?),
+        ))
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, Error> {
+        match self.token.clone() {
+            Some(Token::Variable(name)) => {
+                self.next()?;
+                Ok(Pattern::Variable(name))
+            }
+            Some(Token::OpenBracket) => {
+                self.next()?;
+                let mut members = vec![self.parse_pattern()?];
+                while self.token == Some(Token::Comma) {
+                    self.next()?;
+                    members.push(self.parse_pattern()?);
+                }
+                self.expect(Token::CloseBracket)?;
+                Ok(Pattern::Array(members))
+            }
+            Some(Token::OpenBrace) => {
+                self.next()?;
+                let mut entries = vec![self.parse_pattern_entry()?];
+                while self.token == Some(Token::Comma) {
+                    self.next()?;
+                    entries.push(self.parse_pattern_entry()?);
+                }
+                self.expect(Token::CloseBrace)?;
+                Ok(Pattern::Object(entries))
+            }
+            Some(token) => Err(Error::Parser(format!(
+                "expected variable, array or object pattern, got {}",
+                token
+            ))),
+            None => Err(Error::Parser(
+                "expected pattern, got end of input".to_string(),
+            )),
+        }
+    }
+
+    fn parse_pattern_entry(&mut self) -> Result<(PatternKey, Option<Pattern>), Error> {
+        let key = match self.token.clone() {
+            Some(Token::Variable(name)) => PatternKey::Variable(name),
+            Some(Token::Word(name)) | Some(Token::Keyword(name)) | Some(Token::Str(name)) => {
+                PatternKey::Literal(name)
+            }
+            Some(Token::OpenParen) => {
+                self.next()?;
+                let expr = self.parse_expression(0)?;
+                if self.token != Some(Token::CloseParen) {
+                    return Err(Error::Parser(
+                        "expected close paren in object key".to_string(),
+                    ));
+                }
+                PatternKey::Expression(Rc::new(expr))
+            }
+            Some(token) => {
+                return Err(Error::Parser(format!(
+                    "expected object key in pattern, got {}",
+                    token
+                )))
+            }
+            None => {
+                return Err(Error::Parser(
+                    "expected object key, got end of input".to_string(),
+                ))
+            }
+        };
+        self.next()?;
+        if self.token != Some(Token::Colon) {
+            return match key {
+                PatternKey::Variable(_) => Ok((key, None)),
+                _ => Err(Error::Parser(
+                    "object pattern keys need a pattern, as in {key: $value}".to_string(),
+                )),
+            };
+        }
         self.next()?;
-        while self.token.is_some() {
-            match &self.token {
-                Some(Token::Bar) => {
-                    let g: FilterGroup = Default::default();
-                    filter.add_group(g);
-                    let s: FilterSet = Default::default();
-                    filter.add_set(s);
+        Ok((key, Some(self.parse_pattern()?)))
+    }
+
+    fn parse_primary(&mut self) -> Result<FilterExpression, Error> {
+        match self.token.clone() {
+            Some(Token::Dot) => {
+                self.next()?;
+                if let Some(Token::Str(name)) = &self.token {
+                    let filter = FilterType::Entry(name.to_string());
+                    self.next()?;
+                    return Ok(FilterExpression::Filter(filter));
                 }
-                Some(Token::Comma) => {
-                    filter.change_strategy(DataStrategy::Concat);
-                    let s: FilterSet = Default::default();
-                    filter.add_set(s);
+                Ok(FilterExpression::Filter(FilterType::Current))
+            }
+            Some(Token::Field(name)) => {
+                self.next()?;
+                Ok(FilterExpression::Filter(FilterType::Entry(name)))
+            }
+            Some(Token::DoubleDot) => {
+                self.next()?;
+                Ok(FilterExpression::Filter(FilterType::Recurse))
+            }
+            Some(Token::OpenBracket) => {
+                self.next()?;
+                if self.token == Some(Token::CloseBracket) {
+                    self.next()?;
+                    return Ok(FilterExpression::Literal(Data::Array(Vec::new())));
                 }
-                Some(Token::Dot) => {
-                    if let Some(t) = self.lex.peek()? {
-                        match t {
-                            Token::Word(word) => {
-                                filter.add_filter(FilterType::Entry(word.to_string()));
-                                self.lex.next()?;
-                            }
-                            _ => {
-                                filter.add_filter(FilterType::Current);
-                            }
-                        }
+                let members = self.parse_expression(0)?;
+                self.expect(Token::CloseBracket)?;
+                Ok(FilterExpression::Collect(Rc::new(members)))
+            }
+            Some(Token::OpenParen) => {
+                self.next()?;
+                let filter = self.parse_expression(0)?;
+                self.expect(Token::CloseParen)?;
+                Ok(filter)
+            }
+            Some(Token::Null) => {
+                self.next()?;
+                Ok(FilterExpression::Literal(Data::Null))
+            }
+            Some(Token::Boolean(b)) => {
+                self.next()?;
+                Ok(FilterExpression::Literal(Data::Boolean(b)))
+            }
+            Some(Token::Number(num)) => {
+                self.next()?;
+                Ok(FilterExpression::Literal(ExpressionParser::parse_number(
+                    &num,
+                )?))
+            }
+            Some(Token::Minus) => {
+                self.next()?;
+                if let Some(Token::Number(num)) = &self.token {
+                    let negative = format!("-{}", num);
+                    self.next()?;
+                    return Ok(FilterExpression::Literal(ExpressionParser::parse_number(
+                        &negative,
+                    )?));
+                }
+                let operand = self.parse_term()?;
+                Ok(FilterExpression::Negate(Rc::new(operand)))
+            }
+            Some(Token::Str(string)) => {
+                self.next()?;
+                Ok(FilterExpression::Literal(Data::String(string)))
+            }
+            Some(Token::Template(parts)) => {
+                self.next()?;
+                self.parse_template(None, parts)
+            }
+            Some(Token::Format(name)) => {
+                let format = ExpressionParser::new_format(name)?;
+                self.next()?;
+                match self.token.clone() {
+                    Some(Token::Str(string)) => {
+                        self.next()?;
+                        let parts = vec![StringPart::Literal(string)];
+                        self.parse_template(Some(format), parts)
+                    }
+                    Some(Token::Template(parts)) => {
+                        self.next()?;
+                        self.parse_template(Some(format), parts)
                     }
+                    _ => Ok(FilterExpression::Filter(format)),
                 }
-                Some(Token::OpenBracket) => {
-                    filter.add_filter(self.parse_bracketed_expression()?);
+            }
+            Some(Token::OpenBrace) => {
+                self.next()?;
+                self.parse_object()
+            }
+            Some(Token::Variable(name)) => {
+                if !self.variables.contains(&name) {
+                    return Err(Error::Parser(format!("${} is not defined", name)));
                 }
-                Some(Token::Word(word)) => match word.as_str() {
-                    "keys" => filter.add_filter(FilterType::Keys),
-                    _ => return Err(Error::Parser(format!("unknown keyword: {}", word))),
-                },
-                _ => {
-                    return Err(Error::Parser(format!(
-                        "unexpected token: {}",
-                        self.token.as_ref().unwrap()
-                    )));
+                self.next()?;
+                Ok(FilterExpression::Variable(name))
+            }
+            Some(Token::Keyword(word)) if word == "if" => {
+                self.next()?;
+                self.parse_conditional()
+            }
+            Some(Token::Keyword(word)) if word == "def" => {
+                self.next()?;
+                self.parse_definition()
+            }
+            Some(Token::Keyword(word)) if word == "reduce" || word == "foreach" => {
+                self.next()?;
+                self.parse_fold(word == "foreach")
+            }
+            Some(Token::Keyword(word)) if word == "try" => {
+                self.next()?;
+                self.parse_try()
+            }
+            Some(Token::Word(word)) => {
+                self.next()?;
+                let args = self.parse_arguments()?;
+                let arity = args.len();
+                if self
+                    .functions
+                    .iter()
+                    .rev()
+                    .any(|f| f.0 == word && f.1 == arity)
+                {
+                    let args = args.into_iter().map(Rc::new).collect();
+                    return Ok(FilterExpression::Call(word, args));
                 }
+                builtin(&word, args)
             }
-            self.next()?;
+            Some(token) => Err(Error::Parser(format!("unexpected token: {}", token))),
+            None => Err(Error::Parser("unexpected end of input".to_string())),
         }
+    }
 
-        Ok(filter)
+    /// Parses the interpolated expressions of a string with the variables
+    /// and functions in scope where the string appears.
+    fn parse_template(
+        &mut self,
+        format: Option<FilterType>,
+        parts: Vec<StringPart>,
+    ) -> Result<FilterExpression, Error> {
+        let mut template = Vec::new();
+        for part in parts {
+            template.push(match part {
+                StringPart::Literal(string) => TemplatePart::Literal(string),
+                StringPart::Tokens(tokens) => {
+                    let mut parser = ExpressionParser {
+                        source: TokenSource::Tokens(tokens.into_iter()),
+                        token: None,
+                        variables: self.variables.clone(),
+                        functions: self.functions.clone(),
+                    };
+                    TemplatePart::Expression(Rc::new(parser.parse()?))
+                }
+            });
+        }
+        let format = match format {
+            Some(FilterType::Format(name)) => Some(name),
+            _ => None,
+        };
+        Ok(FilterExpression::Template(format, template))
     }
 
-    fn next(&mut self) -> Result<(), Error> {
-        self.token = self.lex.next()?;
-        Ok(())
+    fn new_format(name: String) -> Result<FilterType, Error> {
+        if !FORMATS.contains(&name.as_str()) {
+            return Err(Error::Parser(format!("@{} is not a valid format", name)));
+        }
+        Ok(FilterType::Format(name))
     }
 
-    fn new_range(start: usize, end: usize) -> Result<FilterType, Error> {
-        return if (end > 0 && start >= end) || (start > 0 && start == end) {
-            Err(Error::Parser(format!(
-                "invalid range: start ({}) has to be less than end ({})",
-                start, end
-            )))
-        } else {
-            Ok(FilterType::Range(start, end))
+    /// Parses the entries of an object construction after its opening brace.
+    fn parse_object(&mut self) -> Result<FilterExpression, Error> {
+        let mut entries = Vec::new();
+        while self.token != Some(Token::CloseBrace) {
+            if !entries.is_empty() {
+                self.expect(Token::Comma)?;
+            }
+            entries.push(self.parse_object_entry()?);
+        }
+        self.next()?;
+        Ok(FilterExpression::Object(entries))
+    }
+
+    fn parse_object_entry(
+        &mut self,
+    ) -> Result<(Rc<FilterExpression>, Rc<FilterExpression>), Error> {
+        let name = match self.token.clone() {
+            Some(Token::Word(name)) | Some(Token::Keyword(name)) | Some(Token::Str(name)) => name,
+            Some(Token::Null) => "null".to_string(),
+            Some(Token::Boolean(b)) => b.to_string(),
+            Some(Token::And) => "and".to_string(),
+            Some(Token::Or) => "or".to_string(),
+            Some(Token::Variable(name)) => {
+                // `{$name}` is shorthand for `{name: $name}`.
+                let value = self.parse_primary()?;
+                return Ok((
+                    Rc::new(FilterExpression::Literal(Data::String(name))),
+                    Rc::new(value),
+                ));
+            }
+            Some(Token::OpenParen) => {
+                let key = self.parse_primary()?;
+                return Ok((Rc::new(key), Rc::new(self.parse_object_value()?)));
+            }
+            Some(Token::Format(name)) => {
+                self.next()?;
+                let key = FilterExpression::Filter(ExpressionParser::new_format(name)?);
+                return Ok((Rc::new(key), Rc::new(self.parse_object_value()?)));
+            }
+            Some(Token::Template(parts)) => {
+                self.next()?;
+                let key = Rc::new(self.parse_template(None, parts)?);
+                if self.token == Some(Token::Colon) {
+                    return Ok((key, Rc::new(self.parse_object_value()?)));
+                }
+                // `{"\(expr)"}` is shorthand for `{"\(expr)": .["\(expr)"]}`.
+                let current = Rc::new(FilterExpression::Filter(FilterType::Current));
+                return Ok((key.clone(), Rc::new(FilterExpression::Index(current, key))));
+            }
+            Some(token) => {
+                return Err(Error::Parser(format!("expected object key, got {}", token)))
+            }
+            None => {
+                return Err(Error::Parser(
+                    "expected object key, got end of input".to_string(),
+                ))
+            }
         };
+        self.next()?;
+        let key = Rc::new(FilterExpression::Literal(Data::String(name.to_string())));
+        if self.token == Some(Token::Colon) {
+            return Ok((key, Rc::new(self.parse_object_value()?)));
+        }
+        // `{name}` is shorthand for `{name: .name}`.
+        Ok((
+            key,
+            Rc::new(FilterExpression::Filter(FilterType::Entry(name))),
+        ))
     }
 
-    fn parse_bracketed_expression(&mut self) -> Result<FilterType, Error> {
-        let mut token_set = Vec::new();
-        let mut is_closed = false;
-        while self.token.is_some() {
+    /// Parses `: value` in an object construction. Commas separate entries,
+    /// so values are pipes of anything binding tighter than a comma.
+    fn parse_object_value(&mut self) -> Result<FilterExpression, Error> {
+        self.expect(Token::Colon)?;
+        let precedence = Operator::Comma.precedence() + 1;
+        let mut values = vec![self.parse_expression(precedence)?];
+        while self.token == Some(Token::Bar) {
             self.next()?;
-            if let Some(Token::CloseBracket) = &self.token {
-                is_closed = true;
-                break;
+            values.push(self.parse_expression(precedence)?);
+        }
+        let mut value = values.pop().unwrap();
+        while let Some(lhs) = values.pop() {
+            value = FilterExpression::binary(Operator::Pipe, lhs, value);
+        }
+        Ok(value)
+    }
+
+    /// Parses the `(a; b)` argument list of a function call, if there is one.
+    fn parse_arguments(&mut self) -> Result<Vec<FilterExpression>, Error> {
+        let mut args = Vec::new();
+        if self.token != Some(Token::OpenParen) {
+            return Ok(args);
+        }
+        self.next()?;
+        args.push(self.parse_expression(0)?);
+        while self.token == Some(Token::Semicolon) {
+            self.next()?;
+            args.push(self.parse_expression(0)?);
+        }
+        self.expect(Token::CloseParen)?;
+        Ok(args)
+    }
+
+    /// Parses `name(params): body;` after `def`, and the expression the function is visible in.
+    fn parse_definition(&mut self) -> Result<FilterExpression, Error> {
+        let name = match self.token.clone() {
+            Some(Token::Word(name)) => name,
+            Some(token) => {
+                return Err(Error::Parser(format!(
+                    "expected function name, got {}",
+                    token
+                )))
+            }
+            None => {
+                return Err(Error::Parser(
+                    "expected function name, got end of input".to_string(),
+                ))
+            }
+        };
+        self.next()?;
+        let mut params = Vec::new();
+        if self.token == Some(Token::OpenParen) {
+            loop {
+                self.next()?;
+                match self.token.clone() {
+                    Some(Token::Word(param)) => params.push((param, false)),
+                    Some(Token::Variable(param)) => params.push((param, true)),
+                    Some(token) => {
+                        return Err(Error::Parser(format!(
+                            "expected parameter name, got {}",
+                            token
+                        )))
+                    }
+                    None => {
+                        return Err(Error::Parser(
+                            "expected parameter name, got end of input".to_string(),
+                        ))
+                    }
+                }
+                self.next()?;
+                if self.token != Some(Token::Semicolon) {
+                    break;
+                }
+            }
+            self.expect(Token::CloseParen)?;
+        }
+        self.expect(Token::Colon)?;
+
+        let (functions, variables) = (self.functions.len(), self.variables.len());
+        self.functions.push((name.to_string(), params.len()));
+        for (param, is_variable) in &params {
+            self.functions.push((param.to_string(), 0));
+            if *is_variable {
+                self.variables.push(param.to_string());
             }
-            token_set.push(self.token.clone().unwrap());
         }
-        if !is_closed {
-            return Err(Error::Parser("bracketed expression not closed".to_string()));
+        let body = self.parse_expression(0);
+        self.functions.truncate(functions + 1);
+        self.variables.truncate(variables);
+        let mut body = body?;
+        self.expect(Token::Semicolon)?;
+
+        // `def f($x): body` is shorthand for `def f(x): x as $x | body`.
+        for (param, is_variable) in params.iter().rev() {
+            if *is_variable {
+                body = FilterExpression::Bind(
+                    Rc::new(FilterExpression::Call(param.to_string(), Vec::new())),
+                    vec![Pattern::Variable(param.to_string())],
+                    Rc::new(body),
+                );
+            }
         }
-        return if token_set.is_empty() {
-            // Empty array expression: []
-            Ok(FilterType::Array)
-        } else if token_set.contains(&Token::Colon) {
-            // Range expression: [(n)?:(m)?]
-            ExpressionParser::parse_range_expression(token_set)
-        } else if token_set.len() == 1 {
-            if let Token::Str(w) = &token_set[0] {
-                // Object property: ["key"]
-                return Ok(FilterType::Entry(w.to_string()));
-            }
-            if let Token::Number(n) = &token_set[0] {
-                // Array member: [n]
-                return Ok(FilterType::Member(n.parse::<usize>().unwrap()));
-            }
-            return Err(Error::Parser(format!(
-                "expected number, string or range in bracketed expression, got {}",
-                &token_set[0]
-            )));
+        let function = Function {
+            name,
+            params: params.into_iter().map(|(param, _)| param).collect(),
+            body: Rc::new(body),
+        };
+        let rest = self.parse_expression(0);
+        self.functions.truncate(functions);
+        Ok(FilterExpression::Define(Rc::new(function), Rc::new(rest?)))
+    }
+
+    /// Parses `source as $x (init; update)` after `reduce`,
+    /// or `source as $x (init; update; extract)` after `foreach`.
+    fn parse_fold(&mut self, foreach: bool) -> Result<FilterExpression, Error> {
+        let source = self.parse_juxtaposed()?;
+        let patterns = self.parse_patterns()?;
+        self.expect(Token::OpenParen)?;
+        let init = self.parse_expression(0)?;
+        self.expect(Token::Semicolon)?;
+
+        let scope = self.variables.len();
+        self.variables
+            .extend(patterns.iter().flat_map(Pattern::variables));
+        let update = self.parse_expression(0);
+        let extract = match update {
+            Ok(_) if foreach && self.token == Some(Token::Semicolon) => {
+                self.next()?;
+                Some(self.parse_expression(0))
+            }
+            _ => None,
+        };
+        self.variables.truncate(scope);
+        let (update, extract) = (Rc::new(update?), extract.transpose()?.map(Rc::new));
+        self.expect(Token::CloseParen)?;
+
+        let (source, init) = (Rc::new(source), Rc::new(init));
+        Ok(if foreach {
+            FilterExpression::Foreach(source, patterns, init, update, extract)
         } else {
-            Err(Error::Parser("invalid bracket expression".to_string()))
+            FilterExpression::Reduce(source, patterns, init, update)
+        })
+    }
+
+    /// Parses `try body` with an optional `catch handler`; both are single terms.
+    fn parse_try(&mut self) -> Result<FilterExpression, Error> {
+        let body = self.parse_juxtaposed()?;
+        let handler = if self.token == Some(Token::Keyword("catch".to_string())) {
+            self.next()?;
+            Some(Rc::new(self.parse_juxtaposed()?))
+        } else {
+            None
         };
+        Ok(FilterExpression::Try(Rc::new(body), handler))
     }
 
-    fn parse_range_expression(token_set: Vec<Token>) -> Result<FilterType, Error> {
-        let mut start: usize = 0;
-        let mut end: usize = 0;
-        if token_set.len() == 1 {
-            // Empty range expression: [:]
-            return ExpressionParser::new_range(start, end);
-        }
+    /// Parses the rest of an `if` (or `elif`) expression, up to and including its `end`.
+    fn parse_conditional(&mut self) -> Result<FilterExpression, Error> {
+        let condition = self.parse_expression(0)?;
+        self.expect(Token::Keyword("then".to_string()))?;
+        let then = self.parse_expression(0)?;
+        let otherwise = match &self.token {
+            Some(Token::Keyword(word)) if word == "elif" => {
+                self.next()?;
+                Some(self.parse_conditional()?)
+            }
+            Some(Token::Keyword(word)) if word == "else" => {
+                self.next()?;
+                let otherwise = self.parse_expression(0)?;
+                self.expect(Token::Keyword("end".to_string()))?;
+                Some(otherwise)
+            }
+            _ => {
+                self.expect(Token::Keyword("end".to_string()))?;
+                None
+            }
+        };
+        Ok(FilterExpression::If(
+            Rc::new(condition),
+            Rc::new(then),
+            otherwise.map(Rc::new),
+        ))
+    }
 
-        let mut pos = 0;
-        if let Token::Number(num) = &token_set[pos] {
-            start = num.parse::<usize>().unwrap();
-            pos += 1;
+    fn expect(&mut self, expected: Token) -> Result<(), Error> {
+        match &self.token {
+            Some(token) if *token == expected => self.next(),
+            Some(token) => Err(Error::Parser(format!(
+                "expected {}, got {}",
+                expected, token
+            ))),
+            None => Err(Error::Parser(format!(
+                "expected {}, got end of input",
+                expected
+            ))),
         }
-        if let Token::Colon = &token_set[pos] {
-            pos += 1;
-        } else if pos == 0 {
-            return Err(Error::Parser(format!(
-                "expected colon or start range, got {}",
-                &token_set[pos]
-            )));
+    }
+
+    fn next(&mut self) -> Result<(), Error> {
+        self.token = self.source.next()?;
+        Ok(())
+    }
+
+    fn new_range(start: usize, end: usize) -> Result<FilterType, Error> {
+        if (end > 0 && start >= end) || (start > 0 && start == end) {
+            Err(Error::Parser(format!(
+                "invalid range: start ({}) has to be less than end ({})",
+                start, end
+            )))
         } else {
-            return Err(Error::Parser(
-                "invalid range, expected colon separator".to_string(),
-            ));
-        }
-        if pos < token_set.len() {
-            if let Token::Number(num) = &token_set[pos] {
-                end = num.parse::<usize>().unwrap();
-            } else {
-                return Err(Error::Parser(format!(
-                    "expected end range, got {}",
-                    &token_set[pos]
-                )));
-            }
+            Ok(FilterType::Range(start, end))
         }
-        ExpressionParser::new_range(start, end)
+    }
+}
+
+/// Slice bound written as a non-negative number, or left out.
+fn literal_bound(bound: &Option<FilterExpression>) -> Option<usize> {
+    match bound {
+        None => Some(0),
+        Some(FilterExpression::Literal(Data::Integer(n))) => usize::try_from(*n).ok(),
+        _ => None,
     }
 }
 
@@ -171,15 +777,26 @@ mod test {
         assert_eq!(format!("{}", filters), ".[]keys");
     }
 
+    #[test]
+    fn parses_keys_unsorted_expr() {
+        let mut parser = ExpressionParser::new("keys_unsorted");
+        let result = parser.parse();
+
+        assert!(result.is_ok(), "should be a success");
+
+        let filters = result.unwrap();
+        assert_eq!(format!("{}", filters), "keys_unsorted");
+    }
+
     #[test]
     fn parses_generic_object_index() {
-        let mut parser = ExpressionParser::new("[\"what\"]");
+        let mut parser = ExpressionParser::new(".[\"what\"]");
         let result = parser.parse();
 
         assert!(result.is_ok(), "should be a success");
 
         let filters = result.unwrap();
-        assert_eq!(format!("{}", filters), "[\"what\"]");
+        assert_eq!(format!("{}", filters), ".what");
     }
 
     #[test]
@@ -190,6 +807,14 @@ mod test {
         assert!(result.is_err(), "should not be a success");
     }
 
+    #[test]
+    fn expects_closed_bracket() {
+        let mut parser = ExpressionParser::new("[|");
+        let result = parser.parse();
+
+        assert!(result.is_err(), "should not be a success");
+    }
+
     #[test]
     fn expects_valid_tokens_sequence() {
         let mut parser = ExpressionParser::new("(what the...)");
@@ -200,46 +825,46 @@ mod test {
 
     #[test]
     fn parses_full_ranges() {
-        let mut parser = ExpressionParser::new("[1:61]");
+        let mut parser = ExpressionParser::new(".[1:61]");
         let result = parser.parse();
 
         assert!(result.is_ok(), "should not be an error");
 
         let filters = result.unwrap();
-        assert_eq!(format!("{}", filters), "[1:61]");
+        assert_eq!(format!("{}", filters), ".[1:61]");
     }
 
     #[test]
     fn parses_no_end_ranges() {
-        let mut parser = ExpressionParser::new("[61:]");
+        let mut parser = ExpressionParser::new(".[61:]");
         let result = parser.parse();
 
         assert!(result.is_ok(), "should not be an error");
 
         let filters = result.unwrap();
-        assert_eq!(format!("{}", filters), "[61:0]");
+        assert_eq!(format!("{}", filters), ".[61:0]");
     }
 
     #[test]
     fn parses_no_start_ranges() {
-        let mut parser = ExpressionParser::new("[:61]");
+        let mut parser = ExpressionParser::new(".[:61]");
         let result = parser.parse();
 
         assert!(result.is_ok(), "should not be an error");
 
         let filters = result.unwrap();
-        assert_eq!(format!("{}", filters), "[0:61]");
+        assert_eq!(format!("{}", filters), ".[0:61]");
     }
 
     #[test]
     fn parses_open_ranges() {
-        let mut parser = ExpressionParser::new("[:]");
+        let mut parser = ExpressionParser::new(".[:]");
         let result = parser.parse();
 
         assert!(result.is_ok(), "should not be an error");
 
         let filters = result.unwrap();
-        assert_eq!(format!("{}", filters), "[0:0]");
+        assert_eq!(format!("{}", filters), ".[0:0]");
     }
 
     #[test]
@@ -253,7 +878,7 @@ mod test {
     }
 
     #[test]
-    fn comma_changes_strategy() {
+    fn comma_binds_tighter_than_bar() {
         let mut parser = ExpressionParser::new("[0],[1]|keys");
         let result = parser.parse();
         assert!(result.is_ok(), "should not be an error");
@@ -261,4 +886,335 @@ mod test {
         let filters = result.unwrap();
         assert_eq!(format!("{}", filters), "[0],[1]|keys");
     }
+
+    #[test]
+    fn parses_parenthesized_expressions() {
+        let mut parser = ExpressionParser::new("[0], ([1] | keys)");
+        let result = parser.parse();
+        assert!(result.is_ok(), "should not be an error");
+
+        let filters = result.unwrap();
+        assert_eq!(format!("{}", filters), "[0],([1]|keys)");
+    }
+
+    #[test]
+    fn drops_redundant_parentheses() {
+        let mut parser = ExpressionParser::new("(([0] | [1])), [2]");
+        let result = parser.parse();
+        assert!(result.is_ok(), "should not be an error");
+
+        let filters = result.unwrap();
+        assert_eq!(format!("{}", filters), "([0]|[1]),[2]");
+    }
+
+    #[test]
+    fn bar_is_right_associative() {
+        let mut parser = ExpressionParser::new("[0] | [1] | keys");
+        let filters = parser.parse().unwrap();
+        assert!(
+            matches!(
+                &filters,
+                FilterExpression::Binary(Operator::Pipe, _, rhs)
+                    if matches!(**rhs, FilterExpression::Binary(Operator::Pipe, _, _))
+            ),
+            "expected right-nested pipe"
+        );
+    }
+
+    #[test]
+    fn parses_comparisons_and_literals() {
+        let mut parser = ExpressionParser::new(".[0] >= 1.50 and .[1] == \"x\" or null != true");
+        let result = parser.parse();
+        assert!(result.is_ok(), "should not be an error");
+
+        let filters = result.unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            ".[0]>=1.50 and .[1]==\"x\" or null!=true"
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let mut parser = ExpressionParser::new("(null or true) and false or true");
+        let filters = parser.parse().unwrap();
+        assert_eq!(format!("{}", filters), "(null or true) and false or true");
+        assert!(
+            matches!(&filters, FilterExpression::Binary(Operator::Or, _, _)),
+            "expected or at the top"
+        );
+    }
+
+    #[test]
+    fn parses_arithmetic_precedence() {
+        let mut parser = ExpressionParser::new("1 + .[0] * -2 - -.[1] % 3 > 0");
+        let result = parser.parse();
+        assert!(result.is_ok(), "should not be an error");
+
+        let filters = result.unwrap();
+        assert_eq!(format!("{}", filters), "1+.[0]*-2--.[1]%3>0");
+        assert!(
+            matches!(&filters, FilterExpression::Binary(Operator::Greater, _, _)),
+            "expected comparison at the top"
+        );
+    }
+
+    #[test]
+    fn rejects_chained_comparisons() {
+        let mut parser = ExpressionParser::new("1 < 2 < 3");
+        let result = parser.parse();
+
+        assert!(result.is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn parses_function_calls() {
+        let mut parser = ExpressionParser::new(".[] | select(.age > 30) | map_values(.name)");
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            ".[]|select(.age>30)|map_values(.name)"
+        );
+    }
+
+    #[test]
+    fn checks_function_arity() {
+        let mut parser = ExpressionParser::new("map(.a; .b)");
+        let result = parser.parse();
+
+        assert!(result.is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn parses_conditionals() {
+        let mut parser =
+            ExpressionParser::new("if .a then 1 elif .b then 2 elif .c then 3 else 4 end");
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            "if .a then 1 elif .b then 2 elif .c then 3 else 4 end"
+        );
+
+        let mut parser = ExpressionParser::new("if . then .end end");
+        let filters = parser.parse().unwrap();
+        assert_eq!(format!("{}", filters), "if . then .end end");
+    }
+
+    #[test]
+    fn expects_conditional_end() {
+        let mut parser = ExpressionParser::new("if . then 1 else 2");
+        let result = parser.parse();
+
+        assert!(result.is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn parses_bindings() {
+        let mut parser =
+            ExpressionParser::new(". as [$a, {b: $c, $d, \"e f\": [$g]}] ?// $a | $a, $g");
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            ". as [$a,{b:$c,$d,\"e f\":[$g]}] ?// $a|$a,$g"
+        );
+    }
+
+    #[test]
+    fn binding_body_extends_right() {
+        let mut parser = ExpressionParser::new("1 + .a as $x | $x | . * 2");
+        let filters = parser.parse().unwrap();
+        assert_eq!(format!("{}", filters), "1+(.a as $x|$x|.*2)");
+    }
+
+    #[test]
+    fn rejects_undefined_variables() {
+        let mut parser = ExpressionParser::new("(. as $x | $x), $x");
+        let result = parser.parse();
+
+        assert!(result.is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn parses_definitions() {
+        let mut parser = ExpressionParser::new("def inc(f; $by): f + $by; def one: 1; inc(one; 2)");
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            "def inc(f;by): by as $by|f+$by; def one: 1; inc(one;2)"
+        );
+    }
+
+    #[test]
+    fn scopes_definitions() {
+        let mut parser = ExpressionParser::new("(def f: 1; f), f");
+        assert!(parser.parse().is_err(), "should not be a success");
+
+        let mut parser = ExpressionParser::new("def f(g): g; g");
+        assert!(parser.parse().is_err(), "should not be a success");
+
+        let mut parser = ExpressionParser::new("def f(g): g; f");
+        assert!(parser.parse().is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn parses_folds() {
+        let mut parser = ExpressionParser::new("reduce .[] as [$a, $b] (0; . + $a * $b)");
+        let filters = parser.parse().unwrap();
+        assert_eq!(format!("{}", filters), "reduce .[] as [$a,$b] (0;.+$a*$b)");
+
+        let mut parser = ExpressionParser::new("foreach .[] as $x (0; . + $x; $x, .)");
+        let filters = parser.parse().unwrap();
+        assert_eq!(format!("{}", filters), "foreach .[] as $x (0;.+$x;$x,.)");
+
+        let mut parser = ExpressionParser::new("reduce .[] as $x ($x; .)");
+        assert!(parser.parse().is_err(), "should not be a success");
+
+        let mut parser = ExpressionParser::new("reduce .[] as $x (0; .; .)");
+        assert!(parser.parse().is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn parses_object_construction() {
+        let mut parser = ExpressionParser::new(
+            ". as $v | {name, \"a b\", $v, if: 1, (.k): .x | .y, @base64: ., c: (1, 2)}",
+        );
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            ". as $v|{name:.name,\"a b\":.\"a b\",v:$v,if:1,(.k):(.x|.y),(@base64):.,c:(1,2)}"
+        );
+
+        let mut parser = ExpressionParser::new("{}");
+        assert_eq!(format!("{}", parser.parse().unwrap()), "{}");
+    }
+
+    #[test]
+    fn rejects_invalid_object_construction() {
+        for source in ["{a: 1, }", "{(.a)}", "{a: 1", "{1: 2}", "{@nope: 1}"] {
+            let mut parser = ExpressionParser::new(source);
+            assert!(
+                parser.parse().is_err(),
+                "{} should not be a success",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn distinguishes_array_construction_from_indexing() {
+        let mut parser = ExpressionParser::new("[.[] | .name], [], .a[0][1:], .[\"a b\"][]");
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            "[.[]|.name],[],.a[0][1:0],.\"a b\"[]"
+        );
+        assert!(
+            matches!(&filters, FilterExpression::Binary(Operator::Comma, lhs, _)
+                if matches!(lhs.as_ref(), FilterExpression::Binary(Operator::Comma, lhs, _)
+                    if matches!(lhs.as_ref(), FilterExpression::Binary(Operator::Comma, lhs, _)
+                        if matches!(lhs.as_ref(), FilterExpression::Collect(_))))),
+            "expected array construction first"
+        );
+    }
+
+    #[test]
+    fn parses_computed_indices() {
+        let mut parser = ExpressionParser::new(". as $k | .[.idx], .[$k], .a[-1], .[1:.n], .[:-1]");
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            ". as $k|.[.idx],.[$k],.a[-1],.[1:.n],.[:-1]"
+        );
+    }
+
+    #[test]
+    fn parses_error_handling() {
+        let mut parser =
+            ExpressionParser::new(r#".a?.b, .[]?, try error("x") catch ., (try .a catch .b) | .c"#);
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            r#".a?.b,.[]?,(try ("x"|error) catch .),(try .a catch .b)|.c"#
+        );
+
+        let mut parser = ExpressionParser::new("try .a catch");
+        assert!(parser.parse().is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn parses_alternatives() {
+        let mut parser = ExpressionParser::new(r#".a // .b // "c", .d // .e and .f | .g"#);
+        let filters = parser.parse().unwrap();
+        assert_eq!(format!("{}", filters), r#".a//.b//"c",.d//.e and .f|.g"#);
+        assert!(
+            matches!(&filters, FilterExpression::Binary(Operator::Pipe, lhs, _)
+                if matches!(lhs.as_ref(), FilterExpression::Binary(Operator::Comma, _, rhs)
+                    if matches!(rhs.as_ref(), FilterExpression::Binary(Operator::Alternative, _, _)))),
+            "expected // between comma and and"
+        );
+
+        let mut parser = ExpressionParser::new("(.a // .b) // .c");
+        let filters = parser.parse().unwrap();
+        assert_eq!(format!("{}", filters), "(.a//.b)//.c");
+    }
+
+    #[test]
+    fn parses_recursion() {
+        let mut parser =
+            ExpressionParser::new("..|.a?, recurse, recurse(.[]?), recurse(.c; . != null)");
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            "..|.a?,..,recurse(.[]?),recurse(.c;.!=null)"
+        );
+    }
+
+    #[test]
+    fn parses_assignments() {
+        let mut parser =
+            ExpressionParser::new(".a = 1 | .b |= . + 1, .c += 2 // 3, .d //= .e or .f");
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            ".a=1|.b|=.+1,.c+=2//3,.d//=.e or .f"
+        );
+        assert!(
+            matches!(&filters, FilterExpression::Binary(Operator::Pipe, lhs, _)
+                if matches!(lhs.as_ref(), FilterExpression::Binary(Operator::Assign, _, _))),
+            "expected assignment to bind tighter than pipe"
+        );
+
+        let mut parser = ExpressionParser::new(".a = .b = 1");
+        assert!(parser.parse().is_err(), "should not be a success");
+
+        let mut parser = ExpressionParser::new(".a == 1 |= not");
+        let filters = parser.parse().unwrap();
+        assert_eq!(format!("{}", filters), ".a==1|=not");
+    }
+
+    #[test]
+    fn parses_string_interpolation() {
+        let mut parser = ExpressionParser::new(
+            r#". as $x | "a \"b\"\n\(.name + "!") \($x)", @base64 "x\(.)y", @json "z""#,
+        );
+        let filters = parser.parse().unwrap();
+        assert_eq!(
+            format!("{}", filters),
+            r#". as $x|"a \"b\"\n\(.name+"!") \($x)",@base64 "x\(.)y",@json "z""#
+        );
+
+        let mut parser = ExpressionParser::new(r#""\($nope)""#);
+        assert!(parser.parse().is_err(), "should not be a success");
+
+        let mut parser = ExpressionParser::new(r#""\(1 2)""#);
+        assert!(parser.parse().is_err(), "should not be a success");
+    }
+
+    #[test]
+    fn expects_closing_paren() {
+        let mut parser = ExpressionParser::new("([0], [1]");
+        let result = parser.parse();
+
+        assert!(result.is_err(), "should not be a success");
+    }
 }